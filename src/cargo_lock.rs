@@ -0,0 +1,152 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use crate::error::Error;
+use crate::workspace::is_workspace_root;
+
+const LOCK_FILE_NAME: &str = "Cargo.lock";
+
+#[derive(Deserialize)]
+struct LockFile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+/// The exact package versions Cargo resolved for a crate or workspace.
+pub struct CargoLock {
+    packages: Vec<LockedPackage>,
+}
+
+impl CargoLock {
+    /// Reads the Cargo.lock that applies to the manifest at `manifest_dir`: the one in that
+    /// directory or, for workspace members, the one at the workspace root. Returns `None` if there
+    /// isn't one.
+    pub fn find(manifest_dir: &Path) -> Result<Option<CargoLock>, Error> {
+        let manifest_dir = fs::canonicalize(manifest_dir).unwrap_or_else(|_| manifest_dir.to_path_buf());
+        // Lock files above the workspace root belong to some other project.
+        let workspace_root = manifest_dir.ancestors().find(|dir| is_workspace_root(dir)).unwrap_or(&manifest_dir);
+        match [manifest_dir.as_path(), workspace_root].iter().map(|dir| dir.join(LOCK_FILE_NAME)).find(|path| path.is_file()) {
+            Some(path) => CargoLock::from_path(path).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /// Reads the Cargo.lock at `path`.
+    pub fn from_path(path: PathBuf) -> Result<CargoLock, Error> {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => return Err(Error::Manifest { path, source: Box::new(err) })
+        };
+        match toml::from_str::<LockFile>(&contents) {
+            Ok(lock_file) => Ok(CargoLock {
+                packages: lock_file.package,
+            }),
+            Err(err) => Err(Error::Manifest { path, source: Box::new(err) })
+        }
+    }
+
+    /// The exact version of `dependency` that the package `name` at `version` was locked to. When
+    /// several versions of `dependency` are locked, it's the one that satisfies `requirement`.
+    pub fn locked_version(&self, name: &str, version: &str, dependency: &str, requirement: Option<&str>) -> Option<String> {
+        let package = self.packages.iter().find(|package| package.name == name && package.version == version)?;
+        let requirement = requirement.and_then(|requirement| VersionReq::parse(requirement).ok());
+        // Entries are "name" when only one version of the package is locked, otherwise
+        // "name version" or "name version (source)".
+        package.dependencies.iter()
+            .map(|entry| entry.split(' ').collect::<Vec<&str>>())
+            .filter(|parts| parts[0] == dependency)
+            .filter_map(|parts| match parts.get(1) {
+                Some(version) => Some(version.to_string()),
+                None => self.packages.iter()
+                    .find(|package| package.name == dependency)
+                    .map(|package| package.version.clone())
+            })
+            .find(|version| match (&requirement, Version::parse(version)) {
+                (Some(requirement), Ok(version)) => requirement.matches(&version),
+                _ => true,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_project;
+
+    const CARGO_LOCK: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "rand 0.7.3",
+ "rand 0.8.5 (registry+https://github.com/rust-lang/crates.io-index)",
+ "serde",
+]
+
+[[package]]
+name = "rand"
+version = "0.7.3"
+
+[[package]]
+name = "rand"
+version = "0.8.5"
+
+[[package]]
+name = "serde"
+version = "1.0.190"
+"#;
+
+    #[test]
+    fn finds_the_lock_file_at_the_workspace_root() {
+        let dir = temp_project("cargo-lock", &[
+            ("Cargo.lock", CARGO_LOCK),
+            ("Cargo.toml", "[workspace]\nmembers = [\"members/*\"]\n"),
+            ("members/app/Cargo.toml", ""),
+        ]);
+        assert!(CargoLock::find(&dir.join("members/app")).unwrap().is_some());
+        let dir = temp_project("cargo-no-lock", &[("Cargo.toml", "")]);
+        assert!(CargoLock::find(&dir).unwrap().is_none());
+    }
+
+    #[test]
+    fn stops_looking_at_the_workspace_root() {
+        let dir = temp_project("cargo-lock-outside", &[
+            ("Cargo.lock", CARGO_LOCK),
+            ("project/Cargo.toml", "[workspace]\nmembers = [\"app\"]\n"),
+            ("project/app/Cargo.toml", ""),
+            ("standalone/Cargo.toml", ""),
+        ]);
+        assert!(CargoLock::find(&dir.join("project/app")).unwrap().is_none());
+        assert!(CargoLock::find(&dir.join("standalone")).unwrap().is_none());
+    }
+
+    #[test]
+    fn looks_up_locked_versions() {
+        let dir = temp_project("cargo-lock-versions", &[("Cargo.lock", CARGO_LOCK)]);
+        let cargo_lock = CargoLock::from_path(dir.join("Cargo.lock")).unwrap();
+        assert_eq!(cargo_lock.locked_version("app", "0.1.0", "serde", Some("1")).as_deref(), Some("1.0.190"));
+        assert_eq!(cargo_lock.locked_version("app", "0.1.0", "serde", None).as_deref(), Some("1.0.190"));
+        assert_eq!(cargo_lock.locked_version("app", "0.1.0", "tokio", Some("1")), None);
+        assert_eq!(cargo_lock.locked_version("app", "0.2.0", "serde", Some("1")), None);
+    }
+
+    #[test]
+    fn picks_the_locked_version_that_satisfies_the_requirement() {
+        let dir = temp_project("cargo-lock-requirements", &[("Cargo.lock", CARGO_LOCK)]);
+        let cargo_lock = CargoLock::from_path(dir.join("Cargo.lock")).unwrap();
+        assert_eq!(cargo_lock.locked_version("app", "0.1.0", "rand", Some("0.8")).as_deref(), Some("0.8.5"));
+        assert_eq!(cargo_lock.locked_version("app", "0.1.0", "rand", Some("0.7")).as_deref(), Some("0.7.3"));
+        assert_eq!(cargo_lock.locked_version("app", "0.1.0", "rand", Some("0.9")), None);
+    }
+}
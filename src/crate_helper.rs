@@ -1,66 +1,258 @@
 use std::path::Path;
-use cargo_toml::{Manifest, Package};
-use cargo_toml::Dependency::{Detailed, Simple};
+use cargo_toml::{DepsSet, Manifest, Package};
+use cargo_toml::Dependency::{Detailed, Inherited, Simple};
+use semver::VersionReq;
+use crate::cargo_lock::CargoLock;
+use crate::error::Error;
+use crate::manifest::{manifest_dir, semver_matches, Dependency, DependencyKind, GitSource, ManifestReader};
+use crate::package_key::BUILD_SYSTEM;
+use crate::workspace::is_workspace_root;
 
-pub struct Dependency {
-    pub name: String,
-    pub version: Option<String>,
-}
+const MANIFEST_FILE_NAME: &str = "Cargo.toml";
+
+/// The name Cargo gives crates.io, for dependencies that don't name a registry.
+pub const CRATES_IO_REGISTRY: &str = "crates-io";
 
+/// A crate's package section and dependencies, read from its Cargo.toml.
 pub struct CrateHelper {
     package: Package,
     pub dependencies: Vec<Dependency>,
 }
 
-#[derive(Debug)]
-pub struct Error {
-    pub msg: String,
-}
-
-impl Error {
-    pub fn with_msg(msg: String) -> Error {
-        Error {
-            msg
-        }
-    }
-}
-
 impl CrateHelper {
+    /// Reads the manifest at `cargo_toml_path`, which must contain a `[package]` section.
     pub fn from_path(cargo_toml_path: impl AsRef<Path>) -> Result<Self, Error> {
+        let cargo_toml_path = cargo_toml_path.as_ref();
         match Manifest::from_path(cargo_toml_path) {
             Ok(manifest) => {
+                let workspace_dependencies = match inherits_target_dependencies(&manifest) {
+                    true => workspace_dependencies(cargo_toml_path, &manifest)?,
+                    false => DepsSet::new(),
+                };
                 match manifest.package {
                     Some(package) => {
                         // Gather dependencies
+                        let manifest_dir = manifest_dir(cargo_toml_path);
+                        let cargo_lock = CargoLock::find(&manifest_dir)?;
                         let mut dependencies: Vec<Dependency> = Vec::new();
-                        for (name, dep) in manifest.dependencies {
-                            let version = match dep {
-                                Simple(version) => Some(version),
-                                Detailed(details) => details.version
-                            };
-                            dependencies.push(Dependency { name, version });
+                        let mut add_dependencies = |deps: DepsSet, kind: DependencyKind| -> Result<(), Error> {
+                            for (name, dep) in deps {
+                                // `Manifest::from_path` fills in inherited dependencies, except for
+                                // those of a `[target]`.
+                                let dep = match dep {
+                                    Inherited(_) => match workspace_dependencies.get(&name) {
+                                        Some(dep) => dep.clone(),
+                                        None => return Err(Error::InvalidManifest {
+                                            path: cargo_toml_path.to_path_buf(),
+                                            reason: format!("{} is inherited, but the workspace doesn't declare it", name),
+                                        })
+                                    },
+                                    dep => dep,
+                                };
+                                let (version, path, real_name, git, registry) = match dep {
+                                    Simple(version) => (Some(version), None, None, None, None),
+                                    Detailed(details) => {
+                                        let git = details.git.map(|url| GitSource {
+                                            url,
+                                            reference: details.branch.or(details.tag).or(details.rev),
+                                        });
+                                        let registry = details.registry.or(details.registry_index);
+                                        (details.version, details.path.map(|path| manifest_dir.join(path)), details.package, git, registry)
+                                    }
+                                    Inherited(_) => return Err(Error::InvalidManifest {
+                                        path: cargo_toml_path.to_path_buf(),
+                                        reason: format!("{} inherits a workspace dependency that is itself inherited", name),
+                                    })
+                                };
+                                // Renamed dependencies (`alias = { package = "real-name" }`) are keyed
+                                // by their alias, but tracked and locked under the real name.
+                                let mut name = real_name.unwrap_or(name);
+                                let mut locked_version = cargo_lock.as_ref()
+                                    .and_then(|cargo_lock| cargo_lock.locked_version(&package.name, package.version(), &name, version.as_deref()));
+                                // A path dependency is built from whatever version is at the path.
+                                if let Some(path) = &path {
+                                    let (path_name, path_version) = match package_at(path) {
+                                        Ok(package) => package,
+                                        Err(err) => return Err(Error::InvalidManifest {
+                                            path: cargo_toml_path.to_path_buf(),
+                                            reason: format!("Can't read path dependency {}: {}", name, err),
+                                        })
+                                    };
+                                    name = path_name;
+                                    locked_version = Some(path_version);
+                                }
+                                let registry = match (&git, &path) {
+                                    (None, None) => Some(registry.unwrap_or_else(|| String::from(CRATES_IO_REGISTRY))),
+                                    _ => None,
+                                };
+                                dependencies.push(Dependency { name, version, path, locked_version, git, registry, kind });
+                            }
+                            Ok(())
+                        };
+                        add_dependencies(manifest.dependencies, DependencyKind::Normal)?;
+                        add_dependencies(manifest.dev_dependencies, DependencyKind::Dev)?;
+                        add_dependencies(manifest.build_dependencies, DependencyKind::Build)?;
+                        // Only a platform's normal dependencies are `Target`; its dev and build
+                        // dependencies are still test-only and build-time.
+                        for target in manifest.target.into_values() {
+                            add_dependencies(target.dependencies, DependencyKind::Target)?;
+                            add_dependencies(target.dev_dependencies, DependencyKind::Dev)?;
+                            add_dependencies(target.build_dependencies, DependencyKind::Build)?;
                         }
                         Ok(CrateHelper {
                             package,
                             dependencies,
                         })
                     },
-                    None => Err(Error {
-                        msg: format!("No package section present in Cargo.toml")
-                    } )
+                    None => Err(Error::InvalidManifest {
+                        path: cargo_toml_path.to_path_buf(),
+                        reason: String::from("No package section present in Cargo.toml"),
+                    })
                 }
             },
-            Err(_) => Err(Error {
-                msg: format!("Can't find Cargo.toml in current path")
+            Err(err) => Err(Error::Manifest {
+                path: cargo_toml_path.to_path_buf(),
+                source: Box::new(err),
             })
         }
     }
+}
 
-    pub fn name(&self) -> String {
+impl ManifestReader for CrateHelper {
+    fn build_system(&self) -> &'static str {
+        BUILD_SYSTEM
+    }
+
+    fn name(&self) -> String {
         self.package.name.clone()
     }
 
-    pub fn version(&self) -> String {
-        self.package.version.clone()
+    fn version(&self) -> String {
+        self.package.version().to_string()
+    }
+
+    fn dependencies(&self) -> &[Dependency] {
+        &self.dependencies
+    }
+
+    fn matching_versions(&self, requirement: &str, versions: &[String]) -> Option<Vec<String>> {
+        // https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html
+        VersionReq::parse(requirement).ok().map(|requirement| semver_matches(&[requirement], versions))
     }
-}
\ No newline at end of file
+}
+
+/// The name and version of the package in the directory of a path dependency. Only its package
+/// section is read, so that path dependencies on each other don't recurse.
+fn package_at(dir: &Path) -> Result<(String, String), Error> {
+    let cargo_toml_path = dir.join(MANIFEST_FILE_NAME);
+    match Manifest::from_path(&cargo_toml_path) {
+        Ok(Manifest { package: Some(package), .. }) => Ok((package.name().to_string(), package.version().to_string())),
+        Ok(_) => Err(Error::InvalidManifest {
+            path: cargo_toml_path,
+            reason: String::from("No package section present in Cargo.toml"),
+        }),
+        Err(err) => Err(Error::Manifest {
+            path: cargo_toml_path,
+            source: Box::new(err),
+        })
+    }
+}
+
+/// Whether any `[target]` table of the manifest inherits a dependency from the workspace.
+fn inherits_target_dependencies(manifest: &Manifest) -> bool {
+    manifest.target.values()
+        .flat_map(|target| target.dependencies.values().chain(target.dev_dependencies.values()).chain(target.build_dependencies.values()))
+        .any(|dep| matches!(dep, Inherited(_)))
+}
+
+/// The `[workspace.dependencies]` of the workspace that the manifest at `cargo_toml_path` belongs
+/// to, with their paths made relative to the workspace root like Cargo does.
+fn workspace_dependencies(cargo_toml_path: &Path, manifest: &Manifest) -> Result<DepsSet, Error> {
+    let manifest_dir = manifest_dir(cargo_toml_path);
+    let (workspace_dir, dependencies) = match &manifest.workspace {
+        Some(workspace) => (manifest_dir, workspace.dependencies.clone()),
+        None => {
+            let hint = manifest.package.as_ref().and_then(|package| package.workspace.as_ref());
+            let workspace_dir = match hint {
+                Some(hint) => manifest_dir.join(hint),
+                None => match manifest_dir.ancestors().skip(1).find(|dir| is_workspace_root(dir)) {
+                    Some(workspace_dir) => workspace_dir.to_path_buf(),
+                    None => return Err(Error::InvalidManifest {
+                        path: cargo_toml_path.to_path_buf(),
+                        reason: String::from("Dependencies are inherited, but no workspace was found"),
+                    })
+                }
+            };
+            let workspace_toml_path = workspace_dir.join(MANIFEST_FILE_NAME);
+            match Manifest::from_path(&workspace_toml_path) {
+                Ok(Manifest { workspace: Some(workspace), .. }) => (workspace_dir, workspace.dependencies),
+                Ok(_) => return Err(Error::InvalidManifest {
+                    path: workspace_toml_path,
+                    reason: String::from("No workspace section present in Cargo.toml"),
+                }),
+                Err(err) => return Err(Error::Manifest {
+                    path: workspace_toml_path,
+                    source: Box::new(err),
+                })
+            }
+        }
+    };
+    Ok(dependencies.into_iter()
+        .map(|(name, mut dep)| {
+            if let Detailed(details) = &mut dep {
+                if let Some(path) = &mut details.path {
+                    *path = workspace_dir.join(&path).display().to_string();
+                }
+            }
+            (name, dep)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_project;
+
+    const WORKSPACE: &str = "[workspace]\nmembers = [\"app\"]\n\n[workspace.dependencies]\nserde = \"1.0\"\nhelper = { path = \"helper\" }\n";
+
+    #[test]
+    fn resolves_inherited_target_dependencies_from_the_workspace() {
+        let dir = temp_project("crate-inherited-target", &[
+            ("Cargo.toml", WORKSPACE),
+            ("helper/Cargo.toml", "[package]\nname = \"helper\"\nversion = \"0.2.0\"\n"),
+            ("app/Cargo.toml", "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[target.'cfg(unix)'.dependencies]\nserde = { workspace = true }\n\n[target.'cfg(unix)'.dev-dependencies]\nhelper = { workspace = true }\n"),
+        ]);
+        let crt = CrateHelper::from_path(dir.join("app/Cargo.toml")).unwrap();
+        let dependencies: Vec<(&str, Option<&str>, Option<&str>, DependencyKind)> = crt.dependencies.iter()
+            .map(|dep| (dep.name.as_str(), dep.version.as_deref(), dep.locked_version.as_deref(), dep.kind))
+            .collect();
+        assert_eq!(dependencies, [
+            ("serde", Some("1.0"), None, DependencyKind::Target),
+            ("helper", None, Some("0.2.0"), DependencyKind::Dev),
+        ]);
+    }
+
+    #[test]
+    fn rejects_target_dependencies_the_workspace_does_not_declare() {
+        let dir = temp_project("crate-inherited-missing", &[
+            ("Cargo.toml", WORKSPACE),
+            ("app/Cargo.toml", "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[target.'cfg(unix)'.dependencies]\ntokio = { workspace = true }\n"),
+        ]);
+        match CrateHelper::from_path(dir.join("app/Cargo.toml")) {
+            Err(Error::InvalidManifest { reason, .. }) => assert!(reason.starts_with("tokio "), "{}", reason),
+            other => panic!("Expected an invalid manifest, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn names_a_path_dependency_that_can_not_be_read() {
+        let dir = temp_project("crate-missing-path", &[
+            ("Cargo.toml", "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\ngone = { path = \"../gone\" }\n"),
+        ]);
+        match CrateHelper::from_path(dir.join("Cargo.toml")) {
+            Err(Error::InvalidManifest { reason, .. }) => assert!(reason.starts_with("Can't read path dependency gone:"), "{}", reason),
+            other => panic!("Expected an invalid manifest, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use async_trait::async_trait;
+use serde::Serialize;
+use crate::error::Error;
+use crate::package_key::PackageKey;
+use crate::store::{MetadataStore, PackageRecord, PackageUpdate};
+use crate::trigger::RecordedBuild;
+
+/// A write that a dry run would have made.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlannedWrite {
+    AddConsumer { package: String, consumer: String },
+    RemoveConsumer { package: String, consumer: String },
+    RemoveDependency { package: String, dependency: String },
+    UpsertPackage { package: String, build_trigger: String, build_target: String, dependencies: Vec<String> },
+    DeleteRecord { package: String },
+}
+
+impl fmt::Display for PlannedWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlannedWrite::AddConsumer { package, consumer } =>
+                write!(f, "add {} as a consumer of {}", consumer, package),
+            PlannedWrite::RemoveConsumer { package, consumer } =>
+                write!(f, "detach consumer {} from {}", consumer, package),
+            PlannedWrite::RemoveDependency { package, dependency } =>
+                write!(f, "remove dependency {} from {}", dependency, package),
+            PlannedWrite::UpsertPackage { package, build_trigger, build_target, dependencies } =>
+                write!(f, "register {} ({} {}) with dependencies [{}]", package, build_trigger, build_target, dependencies.join(", ")),
+            PlannedWrite::DeleteRecord { package } =>
+                write!(f, "delete {}", package),
+        }
+    }
+}
+
+/// A rebuild that a dry run would have started.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlannedRebuild {
+    pub consumer: String,
+    pub dependency: String,
+    pub build_trigger: String,
+    pub build_target: String,
+}
+
+impl From<RecordedBuild> for PlannedRebuild {
+    fn from(build: RecordedBuild) -> Self {
+        PlannedRebuild {
+            consumer: build.consumer.to_fq_key(),
+            dependency: build.dependency.to_fq_key(),
+            build_trigger: build.target.kind.to_string(),
+            build_target: build.target.target,
+        }
+    }
+}
+
+impl fmt::Display for PlannedRebuild {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rebuild {} via {} {} (due to {})", self.consumer, self.build_trigger, self.build_target, self.dependency)
+    }
+}
+
+/// Everything a dry run would have done, in the order it would have been done.
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub writes: Vec<PlannedWrite>,
+    pub rebuilds: Vec<PlannedRebuild>,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Planned writes:")?;
+        if self.writes.is_empty() {
+            writeln!(f, "  (none)")?;
+        }
+        for write in &self.writes {
+            writeln!(f, "  {}", write)?;
+        }
+        writeln!(f, "Planned rebuilds:")?;
+        if self.rebuilds.is_empty() {
+            writeln!(f, "  (none)")?;
+        }
+        for rebuild in &self.rebuilds {
+            writeln!(f, "  {}", rebuild)?;
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a [`MetadataStore`] so that reads go through to it while writes are only recorded.
+///
+/// Records touched by a planned write are copied into an overlay and the write is applied to the
+/// copy, so later reads and conditional writes see the store as a real run would have left it.
+/// A write is only planned if it would have applied.
+pub struct DryRunStore {
+    inner: Arc<dyn MetadataStore>,
+    writes: Mutex<Vec<PlannedWrite>>,
+    /// Records as the planned writes left them, keyed by fully qualified key. `None` is a record
+    /// that was deleted.
+    overlay: Mutex<BTreeMap<String, Option<PackageRecord>>>,
+}
+
+impl DryRunStore {
+    pub fn new(inner: Arc<dyn MetadataStore>) -> DryRunStore {
+        DryRunStore {
+            inner,
+            writes: Mutex::new(vec![]),
+            overlay: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The writes planned so far.
+    pub fn planned_writes(&self) -> Vec<PlannedWrite> {
+        self.writes.lock().expect("Dry run lock was poisoned").clone()
+    }
+
+    fn overlay(&self) -> MutexGuard<'_, BTreeMap<String, Option<PackageRecord>>> {
+        self.overlay.lock().expect("Dry run lock was poisoned")
+    }
+
+    /// Applies `update` to the package's record as the dry run has left it, and plans `write` if
+    /// the record exists. Returns the record as it was before the update.
+    async fn plan(&self, package: &PackageKey, write: PlannedWrite, update: impl FnOnce(&mut Option<PackageRecord>)) -> Result<Option<PackageRecord>, Error> {
+        let fq_key = package.to_fq_key();
+        let copied = self.overlay().contains_key(&fq_key);
+        let fetched = match copied {
+            true => None,
+            false => self.inner.get_record(package).await?,
+        };
+        let mut overlay = self.overlay();
+        // Another write may have copied the record in while it was being fetched.
+        let record = overlay.entry(fq_key).or_insert(fetched);
+        let old_record = record.clone();
+        update(record);
+        if record.is_some() {
+            self.writes.lock().expect("Dry run lock was poisoned").push(write);
+        }
+        Ok(old_record)
+    }
+}
+
+#[async_trait]
+impl MetadataStore for DryRunStore {
+    async fn add_consumer(&self, dependency: &PackageKey, consumer: &PackageKey) -> Result<bool, Error> {
+        let write = PlannedWrite::AddConsumer {
+            package: dependency.to_fq_key(),
+            consumer: consumer.to_fq_key(),
+        };
+        let old_record = self.plan(dependency, write, |record| if let Some(record) = record {
+            record.consumers.insert(consumer.to_fq_key());
+        }).await?;
+        Ok(old_record.is_some())
+    }
+
+    async fn remove_consumer(&self, dependency: &PackageKey, consumer: &PackageKey) -> Result<bool, Error> {
+        let write = PlannedWrite::RemoveConsumer {
+            package: dependency.to_fq_key(),
+            consumer: consumer.to_fq_key(),
+        };
+        let old_record = self.plan(dependency, write, |record| if let Some(record) = record {
+            record.consumers.remove(&consumer.to_fq_key());
+        }).await?;
+        Ok(old_record.is_some())
+    }
+
+    async fn remove_dependency(&self, consumer: &PackageKey, dependency: &PackageKey) -> Result<bool, Error> {
+        let write = PlannedWrite::RemoveDependency {
+            package: consumer.to_fq_key(),
+            dependency: dependency.to_fq_key(),
+        };
+        let old_record = self.plan(consumer, write, |record| if let Some(record) = record {
+            record.dependencies.remove(&dependency.to_fq_key());
+            record.dependency_kinds.remove(&dependency.to_fq_key());
+        }).await?;
+        Ok(old_record.is_some())
+    }
+
+    async fn upsert_package(&self, package: &PackageKey, update: PackageUpdate) -> Result<Option<PackageRecord>, Error> {
+        let write = PlannedWrite::UpsertPackage {
+            package: package.to_fq_key(),
+            build_trigger: update.build_target.kind.to_string(),
+            build_target: update.build_target.target.clone(),
+            dependencies: update.dependencies.keys().cloned().collect(),
+        };
+        self.plan(package, write, |record| {
+            *record = Some(PackageRecord {
+                key: package.clone(),
+                build_target: Some(update.build_target),
+                consumers: record.take().map(|record| record.consumers).unwrap_or_default(),
+                dependencies: update.dependencies.keys().cloned().collect(),
+                dependency_kinds: update.dependencies,
+            });
+        }).await
+    }
+
+    async fn get_record(&self, package: &PackageKey) -> Result<Option<PackageRecord>, Error> {
+        let copied = self.overlay().get(&package.to_fq_key()).cloned();
+        if let Some(record) = copied {
+            return Ok(record);
+        }
+        self.inner.get_record(package).await
+    }
+
+    async fn delete_record(&self, package: &PackageKey) -> Result<Option<PackageRecord>, Error> {
+        let write = PlannedWrite::DeleteRecord {
+            package: package.to_fq_key(),
+        };
+        // The write is only planned if there's a record left after the update, so check first.
+        let old_record = self.get_record(package).await?;
+        if old_record.is_some() {
+            self.writes.lock().expect("Dry run lock was poisoned").push(write);
+            self.overlay().insert(package.to_fq_key(), None);
+        }
+        Ok(old_record)
+    }
+
+    async fn list_versions(&self, build_system: &str, name: &str) -> Result<Vec<String>, Error> {
+        let mut versions: BTreeSet<String> = self.inner.list_versions(build_system, name).await?.into_iter().collect();
+        for (fq_key, record) in self.overlay().iter() {
+            let pkg_key = PackageKey::from_fq_key(fq_key)?;
+            if pkg_key.build_system == build_system && pkg_key.name == name {
+                match record {
+                    Some(_) => versions.insert(pkg_key.version),
+                    None => versions.remove(&pkg_key.version),
+                };
+            }
+        }
+        Ok(versions.into_iter().collect())
+    }
+
+    async fn list_records(&self) -> Result<Vec<PackageRecord>, Error> {
+        let mut records: BTreeMap<String, Option<PackageRecord>> = self.inner.list_records().await?.into_iter()
+            .map(|record| (record.key.to_fq_key(), Some(record)))
+            .collect();
+        records.extend(self.overlay().iter().map(|(fq_key, record)| (fq_key.clone(), record.clone())));
+        Ok(records.into_values().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::DependencyKind::Normal;
+    use crate::store::{contract, MemoryStore};
+    use crate::trigger::{BuildTarget, TriggerKind};
+
+    fn key(fq_key: &str) -> PackageKey {
+        PackageKey::from_fq_key(fq_key).unwrap()
+    }
+
+    /// `rust/app` consumes `rust/lib`.
+    async fn inner() -> Arc<MemoryStore> {
+        let store = Arc::new(MemoryStore::new());
+        store.track("rust/lib:1.0.0", "lib-project", &[]).await;
+        store.track("rust/app:0.1.0", "app-project", &[("rust/lib:1.0.0", Normal)]).await;
+        store
+    }
+
+    async fn fq_keys(store: &dyn MetadataStore) -> Vec<String> {
+        store.list_records().await.unwrap().iter().map(|record| record.key.to_fq_key()).collect()
+    }
+
+    #[tokio::test]
+    async fn never_writes_to_the_inner_store() {
+        let inner = inner().await;
+        let store = DryRunStore::new(inner.clone());
+        store.add_consumer(&key("rust/lib:1.0.0"), &key("rust/other:1.0.0")).await.unwrap();
+        store.remove_dependency(&key("rust/app:0.1.0"), &key("rust/lib:1.0.0")).await.unwrap();
+        store.delete_record(&key("rust/app:0.1.0")).await.unwrap();
+        let update = PackageUpdate {
+            build_target: BuildTarget { kind: TriggerKind::Noop, target: String::from("new-lib-project") },
+            dependencies: BTreeMap::new(),
+        };
+        store.upsert_package(&key("rust/lib:1.0.0"), update).await.unwrap();
+        assert_eq!(store.planned_writes().len(), 4);
+
+        assert_eq!(fq_keys(inner.as_ref()).await, ["rust/app:0.1.0", "rust/lib:1.0.0"]);
+        let lib = inner.get_record(&key("rust/lib:1.0.0")).await.unwrap().unwrap();
+        assert_eq!(lib.consumers, BTreeSet::from([String::from("rust/app:0.1.0")]));
+        assert_eq!(lib.build_target.unwrap().target, "lib-project");
+        let app = inner.get_record(&key("rust/app:0.1.0")).await.unwrap().unwrap();
+        assert_eq!(app.dependencies, BTreeSet::from([String::from("rust/lib:1.0.0")]));
+    }
+
+    #[tokio::test]
+    async fn reads_see_the_planned_writes() {
+        let store = DryRunStore::new(inner().await);
+        assert!(store.add_consumer(&key("rust/lib:1.0.0"), &key("rust/other:1.0.0")).await.unwrap());
+        assert!(store.remove_dependency(&key("rust/app:0.1.0"), &key("rust/lib:1.0.0")).await.unwrap());
+
+        let lib = store.get_record(&key("rust/lib:1.0.0")).await.unwrap().unwrap();
+        assert_eq!(lib.consumers, BTreeSet::from([String::from("rust/app:0.1.0"), String::from("rust/other:1.0.0")]));
+        let app = store.get_record(&key("rust/app:0.1.0")).await.unwrap().unwrap();
+        assert!(app.dependencies.is_empty());
+        let listed = store.list_records().await.unwrap();
+        assert!(listed.iter().any(|record| record.key.to_fq_key() == "rust/lib:1.0.0" && record.consumers.len() == 2));
+        assert_eq!(store.planned_writes().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_delete_hides_the_record() {
+        let store = DryRunStore::new(inner().await);
+        let deleted = store.delete_record(&key("rust/lib:1.0.0")).await.unwrap().unwrap();
+        assert_eq!(deleted.consumers, BTreeSet::from([String::from("rust/app:0.1.0")]));
+
+        assert!(store.get_record(&key("rust/lib:1.0.0")).await.unwrap().is_none());
+        assert!(store.list_versions("rust", "lib").await.unwrap().is_empty());
+        assert_eq!(fq_keys(&store).await, ["rust/app:0.1.0"]);
+        assert!(!store.add_consumer(&key("rust/lib:1.0.0"), &key("rust/other:1.0.0")).await.unwrap());
+        assert!(store.delete_record(&key("rust/lib:1.0.0")).await.unwrap().is_none());
+        assert_eq!(store.planned_writes().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn plans_only_writes_that_would_apply() {
+        let store = DryRunStore::new(inner().await);
+        let (missing, app) = (key("rust/missing:1.0.0"), key("rust/app:0.1.0"));
+        assert!(!store.add_consumer(&missing, &app).await.unwrap());
+        assert!(!store.remove_consumer(&missing, &app).await.unwrap());
+        assert!(!store.remove_dependency(&missing, &app).await.unwrap());
+        assert!(store.delete_record(&missing).await.unwrap().is_none());
+        assert!(store.planned_writes().is_empty());
+        assert!(store.get_record(&missing).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn behaves_like_a_store() {
+        contract::edge_writes_need_a_tracked_record(&DryRunStore::new(Arc::new(MemoryStore::new()))).await;
+        contract::upserts_keep_consumers(&DryRunStore::new(Arc::new(MemoryStore::new()))).await;
+        contract::deletes_return_the_record_once(&DryRunStore::new(Arc::new(MemoryStore::new()))).await;
+        contract::lists_records_in_key_order(&DryRunStore::new(Arc::new(MemoryStore::new()))).await;
+    }
+}
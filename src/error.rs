@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Exit code for anything that doesn't fit a more specific category.
+pub const EXIT_OTHER: i32 = 1;
+/// Exit code for missing or malformed configuration and arguments.
+pub const EXIT_CONFIG: i32 = 2;
+/// Exit code for manifests that can't be read or don't describe a package.
+pub const EXIT_MANIFEST: i32 = 3;
+/// Exit code for a package metadata table that doesn't exist.
+pub const EXIT_TABLE_NOT_FOUND: i32 = 4;
+/// Exit code for requests rejected because the store is throttling.
+pub const EXIT_THROTTLED: i32 = 5;
+/// Exit code for any other failure reading or writing package metadata.
+pub const EXIT_STORE: i32 = 6;
+/// Exit code for failures starting a rebuild.
+pub const EXIT_TRIGGER: i32 = 7;
+/// Exit code for commands about a package that isn't being tracked.
+pub const EXIT_NOT_TRACKED: i32 = 8;
+/// Exit code for rebuilds that were waited for and didn't succeed.
+pub const EXIT_REBUILD_FAILED: i32 = 9;
+/// Exit code for rebuilds that were still running when the wait timed out.
+pub const EXIT_REBUILD_TIMEOUT: i32 = 10;
+/// Exit code for rebuilds refused because they would go around a dependency cycle.
+pub const EXIT_DEPENDENCY_CYCLE: i32 = 11;
+
+/// Everything that can go wrong while tracking packages.
+///
+/// Conditional writes against untracked packages aren't errors; stores report them by returning
+/// `false` so that callers can skip them.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Unable to read manifest {}: {source}", path.display())]
+    Manifest {
+        path: PathBuf,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("Invalid manifest {}: {reason}", path.display())]
+    InvalidManifest {
+        path: PathBuf,
+        reason: String,
+    },
+
+    #[error("{0}")]
+    MissingConfig(String),
+
+    #[error("Fully qualified key \"{0}\" is in unexpected format.")]
+    InvalidPackageKey(String),
+
+    #[error("{0} is not being tracked.")]
+    NotTracked(String),
+
+    #[error("Package metadata table {0} doesn't exist.")]
+    TableNotFound(String),
+
+    #[error("Package metadata store is throttling requests: {0}")]
+    Throttled(#[source] BoxError),
+
+    #[error("Package metadata store request failed: {0}")]
+    Store(#[source] BoxError),
+
+    #[error("No build trigger configured for {0}.")]
+    TriggerNotConfigured(String),
+
+    #[error("Unable to rebuild via {target}: {source}")]
+    Trigger {
+        target: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("Rebuilds failed: {0}")]
+    RebuildsFailed(String),
+
+    #[error("Timed out waiting for rebuilds: {0}")]
+    RebuildTimeout(String),
+
+    /// The keys of the packages on the cycle, from a package through its consumers back to itself.
+    #[error("Refusing to rebuild through dependency cycle {}", .0.join(" -> "))]
+    DependencyCycle(Vec<String>),
+
+    #[error("Unable to write output: {0}")]
+    Output(#[source] BoxError),
+}
+
+impl Error {
+    /// The process exit code for this error's category.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::MissingConfig(_) | Error::InvalidPackageKey(_) => EXIT_CONFIG,
+            Error::Manifest { .. } | Error::InvalidManifest { .. } => EXIT_MANIFEST,
+            Error::TableNotFound(_) => EXIT_TABLE_NOT_FOUND,
+            Error::Throttled(_) => EXIT_THROTTLED,
+            Error::Store(_) => EXIT_STORE,
+            Error::TriggerNotConfigured(_) | Error::Trigger { .. } => EXIT_TRIGGER,
+            Error::NotTracked(_) => EXIT_NOT_TRACKED,
+            Error::RebuildsFailed(_) => EXIT_REBUILD_FAILED,
+            Error::RebuildTimeout(_) => EXIT_REBUILD_TIMEOUT,
+            Error::DependencyCycle(_) => EXIT_DEPENDENCY_CYCLE,
+            Error::Output(_) => EXIT_OTHER,
+        }
+    }
+}
@@ -0,0 +1,537 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use crate::error::Error;
+use crate::manifest::DependencyKind;
+use crate::package_key::PackageKey;
+use crate::store::{MetadataStore, PackageRecord};
+use crate::trigger::{BuildTarget, TriggerKind};
+
+/// Everything that's rebuilt, directly or through other consumers, when a package changes.
+///
+/// An edge from a dependency to a consumer is only followed if the consumer declared the
+/// dependency with one of the kinds that trigger rebuilds.
+pub struct ConsumerGraph {
+    root: String,
+    /// Records of the root and every consumer reached from it, keyed by fully qualified key.
+    records: BTreeMap<String, PackageRecord>,
+    /// The consumers of each package in the graph that are rebuilt when it changes.
+    edges: BTreeMap<String, BTreeSet<String>>,
+    /// The package each consumer was first reached from, which its rebuild is attributed to.
+    upstreams: BTreeMap<String, String>,
+}
+
+impl ConsumerGraph {
+    /// Walks the consumers of `root` (starting with `consumers`, its direct consumers) and theirs
+    /// in turn. Consumers in `built_together` are part of the build that changed the root, so
+    /// they aren't walked. Edges back to the root are kept, so that cycles through it are found.
+    pub async fn walk(store: &dyn MetadataStore, root: &PackageKey, consumers: &BTreeSet<String>, rebuild_kinds: &BTreeSet<DependencyKind>, built_together: &HashSet<String>) -> Result<ConsumerGraph, Error> {
+        let mut graph = ConsumerGraph {
+            root: root.to_fq_key(),
+            records: BTreeMap::new(),
+            edges: BTreeMap::new(),
+            upstreams: BTreeMap::new(),
+        };
+        if let Some(record) = store.get_record(root).await? {
+            graph.records.insert(graph.root.clone(), record);
+        }
+        // A consumer's own consumers are queued the first time an edge to it is followed, which
+        // isn't necessarily when its record was first looked up.
+        let mut expanded = HashSet::from([graph.root.clone()]);
+        let mut queue = VecDeque::from([(graph.root.clone(), consumers.clone())]);
+        while let Some((dependency, consumers)) = queue.pop_front() {
+            for consumer in consumers {
+                let consumer_key = PackageKey::from_fq_key(&consumer)?;
+                if consumer != graph.root && built_together.contains(&consumer_key.qualified_name()) {
+                    log::info!("{} is part of this build. Skipping rebuild...", consumer);
+                    continue;
+                }
+                if !graph.records.contains_key(&consumer) {
+                    match store.get_record(&consumer_key).await? {
+                        Some(record) => graph.records.insert(consumer.clone(), record),
+                        None => continue
+                    };
+                }
+                let record = &graph.records[&consumer];
+                if !record.dependencies.contains(&dependency) {
+                    log::info!("Dependencies of {} do not contain {}", consumer, dependency);
+                    continue;
+                }
+                let kinds = record.kinds_of(&dependency);
+                if kinds.is_disjoint(rebuild_kinds) {
+                    let kinds: Vec<&str> = kinds.iter().map(|kind| kind.as_str()).collect();
+                    log::info!("{} is only a {} dependency of {}. Skipping rebuild...", dependency, kinds.join("/"), consumer);
+                    continue;
+                }
+                graph.edges.entry(dependency.clone()).or_default().insert(consumer.clone());
+                if expanded.insert(consumer.clone()) {
+                    graph.upstreams.insert(consumer.clone(), dependency.clone());
+                    queue.push_back((consumer, record.consumers.clone()));
+                }
+            }
+        }
+        // Consumers that were looked up but never linked by a followed edge aren't rebuilt.
+        let reached: BTreeSet<String> = graph.edges.values().flatten().cloned().collect();
+        let root = graph.root.clone();
+        graph.records.retain(|consumer, _| *consumer == root || reached.contains(consumer));
+        Ok(graph)
+    }
+
+    /// The record of a consumer in the graph.
+    pub fn record(&self, consumer: &str) -> Option<&PackageRecord> {
+        self.records.get(consumer)
+    }
+
+    /// The package whose edge to `consumer` was followed to reach it: the root for its direct
+    /// consumers, and a consumer of the root for the rest.
+    pub fn upstream(&self, consumer: &str) -> Option<&str> {
+        self.upstreams.get(consumer).map(String::as_str)
+    }
+
+    /// A cycle of consumers in the graph, if there is one, as the path of keys from a package
+    /// through its consumers back to itself. Rebuilding through a cycle would never end, since
+    /// each rebuild would in turn rebuild the package that started it.
+    pub fn cycle(&self) -> Option<Vec<String>> {
+        cycles(&self.edges).into_iter().next()
+    }
+
+    /// Consumers grouped into waves, in the order they're rebuilt: each consumer is in a later
+    /// wave than every dependency it has in the graph, so the packages in a wave can be built
+    /// together once the waves before it are done.
+    ///
+    /// Consumers in a [cycle](ConsumerGraph::cycle), and those that consume one through the
+    /// graph, can't be ordered and are left out.
+    pub fn waves(&self) -> Vec<Vec<String>> {
+        // https://en.wikipedia.org/wiki/Topological_sorting#Kahn's_algorithm
+        let mut in_degrees: BTreeMap<&String, usize> = self.records.keys()
+            .filter(|consumer| **consumer != self.root)
+            .map(|consumer| (consumer, 0))
+            .collect();
+        for (dependency, consumers) in &self.edges {
+            if *dependency == self.root {
+                continue;
+            }
+            for consumer in consumers.iter().filter(|consumer| **consumer != self.root) {
+                *in_degrees.entry(consumer).or_default() += 1;
+            }
+        }
+
+        let mut waves = vec![];
+        let mut wave: Vec<String> = in_degrees.iter()
+            .filter(|(_, in_degree)| **in_degree == 0)
+            .map(|(consumer, _)| consumer.to_string())
+            .collect();
+        while !wave.is_empty() {
+            let mut next_wave = vec![];
+            for consumer in &wave {
+                in_degrees.remove(consumer);
+                for dependent in self.edges.get(consumer).into_iter().flatten() {
+                    if let Some(in_degree) = in_degrees.get_mut(dependent) {
+                        *in_degree -= 1;
+                        if *in_degree == 0 {
+                            next_wave.push(dependent.clone());
+                        }
+                    }
+                }
+            }
+            waves.push(wave);
+            next_wave.sort();
+            wave = next_wave;
+        }
+        waves
+    }
+}
+
+/// Every tracked package and the edges from each dependency to its consumers, for drawing.
+pub struct PackageGraph {
+    /// Each package's build target, keyed by fully qualified key. Dependencies without a record of
+    /// their own have none.
+    nodes: BTreeMap<String, Option<BuildTarget>>,
+    /// The consumers of each package, with the kinds they declared it as.
+    edges: BTreeMap<String, BTreeMap<String, BTreeSet<DependencyKind>>>,
+}
+
+impl PackageGraph {
+    /// The graph of every package in `records`, with edges taken from their dependencies.
+    pub fn from_records(records: Vec<PackageRecord>) -> PackageGraph {
+        let mut graph = PackageGraph {
+            nodes: BTreeMap::new(),
+            edges: BTreeMap::new(),
+        };
+        for record in records {
+            let consumer = record.key.to_fq_key();
+            for dependency in &record.dependencies {
+                graph.nodes.entry(dependency.clone()).or_insert(None);
+                graph.edges.entry(dependency.clone()).or_default().insert(consumer.clone(), record.kinds_of(dependency));
+            }
+            graph.nodes.insert(consumer, record.build_target);
+        }
+        graph
+    }
+
+    /// Only `root` and the packages that consume it, directly or through other consumers.
+    pub fn subtree(self, root: &PackageKey) -> Result<PackageGraph, Error> {
+        let root = root.to_fq_key();
+        if !self.nodes.contains_key(&root) {
+            return Err(Error::NotTracked(root));
+        }
+        let mut reached = BTreeSet::from([root.clone()]);
+        let mut queue = VecDeque::from([root]);
+        while let Some(dependency) = queue.pop_front() {
+            for consumer in self.edges.get(&dependency).into_iter().flat_map(|consumers| consumers.keys()) {
+                if reached.insert(consumer.clone()) {
+                    queue.push_back(consumer.clone());
+                }
+            }
+        }
+        Ok(PackageGraph {
+            nodes: self.nodes.into_iter().filter(|(node, _)| reached.contains(node)).collect(),
+            edges: self.edges.into_iter().filter(|(dependency, _)| reached.contains(dependency)).collect(),
+        })
+    }
+
+    /// The graph in Graphviz's DOT language. Nodes are labeled with their key and build target,
+    /// and edges point from a dependency to its consumer.
+    ///
+    /// https://graphviz.org/doc/info/lang.html
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph packages {\n    rankdir=LR;\n    node [shape=box];\n");
+        for (node, build_target) in &self.nodes {
+            let label = match build_target {
+                Some(build_target) => format!("{}\\n{}", dot_escape(node), dot_escape(&target_label(build_target))),
+                None => dot_escape(node),
+            };
+            dot.push_str(&format!("    \"{}\" [label=\"{}\"];\n", dot_escape(node), label));
+        }
+        for (dependency, consumers) in &self.edges {
+            for (consumer, kinds) in consumers {
+                let attributes = match kinds_label(kinds) {
+                    Some(label) => format!(" [label=\"{}\"]", label),
+                    None => String::new(),
+                };
+                dot.push_str(&format!("    \"{}\" -> \"{}\"{};\n", dot_escape(dependency), dot_escape(consumer), attributes));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The graph as a Mermaid flowchart, labeled like [`PackageGraph::to_dot`].
+    ///
+    /// https://mermaid.js.org/syntax/flowchart.html
+    pub fn to_mermaid(&self) -> String {
+        // Keys aren't valid Mermaid ids, so nodes are numbered and labeled with their key.
+        let ids: HashMap<&str, String> = self.nodes.keys()
+            .enumerate()
+            .map(|(index, node)| (node.as_str(), format!("n{}", index)))
+            .collect();
+        let mut mermaid = String::from("flowchart LR\n");
+        for (node, build_target) in &self.nodes {
+            let label = match build_target {
+                Some(build_target) => format!("{}<br/>{}", mermaid_escape(node), mermaid_escape(&target_label(build_target))),
+                None => mermaid_escape(node),
+            };
+            mermaid.push_str(&format!("    {}[\"{}\"]\n", ids[node.as_str()], label));
+        }
+        for (dependency, consumers) in &self.edges {
+            for (consumer, kinds) in consumers {
+                let arrow = match kinds_label(kinds) {
+                    Some(label) => format!("-->|{}|", label),
+                    None => String::from("-->"),
+                };
+                mermaid.push_str(&format!("    {} {} {}\n", ids[dependency.as_str()], arrow, ids[consumer.as_str()]));
+            }
+        }
+        mermaid
+    }
+}
+
+fn dot_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn mermaid_escape(value: &str) -> String {
+    value.replace('"', "#quot;")
+}
+
+/// The CodeBuild project name, or the kind and target for other triggers.
+fn target_label(build_target: &BuildTarget) -> String {
+    match build_target.kind {
+        TriggerKind::CodeBuild => build_target.target.clone(),
+        _ => build_target.to_string(),
+    }
+}
+
+/// The kinds of an edge, unless it's only a normal dependency.
+fn kinds_label(kinds: &BTreeSet<DependencyKind>) -> Option<String> {
+    if kinds.len() == 1 && kinds.contains(&DependencyKind::Normal) {
+        return None;
+    }
+    let kinds: Vec<&str> = kinds.iter().map(|kind| kind.as_str()).collect();
+    Some(kinds.join("/"))
+}
+
+/// Every cycle among the stored records, each as the path of keys from a package through its
+/// consumers back to itself. Only dependencies declared with one of `rebuild_kinds` are followed,
+/// since the others never cause a rebuild.
+pub fn find_cycles(records: &[PackageRecord], rebuild_kinds: &BTreeSet<DependencyKind>) -> Vec<Vec<String>> {
+    let mut edges: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for record in records {
+        for dependency in &record.dependencies {
+            if !record.kinds_of(dependency).is_disjoint(rebuild_kinds) {
+                edges.entry(dependency.clone()).or_default().insert(record.key.to_fq_key());
+            }
+        }
+    }
+    cycles(&edges)
+}
+
+/// One cycle through each strongly connected component of `edges` that has one, starting from
+/// the component's first key.
+fn cycles(edges: &BTreeMap<String, BTreeSet<String>>) -> Vec<Vec<String>> {
+    let mut components = StronglyConnected::new(edges);
+    for node in edges.keys() {
+        if !components.indices.contains_key(node.as_str()) {
+            components.visit(node);
+        }
+    }
+    let mut cycles = vec![];
+    for component in components.components {
+        let component: BTreeSet<&str> = component.into_iter().collect();
+        let start = match component.iter().next() {
+            Some(start) => *start,
+            None => continue
+        };
+        if component.len() > 1 || edges.get(start).is_some_and(|consumers| consumers.contains(start)) {
+            cycles.push(shortest_cycle(edges, &component, start));
+        }
+    }
+    cycles.sort();
+    cycles
+}
+
+/// The shortest path from `start` through `component` back to `start`, which must be on a cycle.
+fn shortest_cycle(edges: &BTreeMap<String, BTreeSet<String>>, component: &BTreeSet<&str>, start: &str) -> Vec<String> {
+    let mut parents: HashMap<&str, &str> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        for next in edges.get(node).into_iter().flatten().map(String::as_str) {
+            if next == start {
+                let mut path = vec![start.to_string(), node.to_string()];
+                let mut current = node;
+                while let Some(parent) = parents.get(current) {
+                    path.push(parent.to_string());
+                    current = parent;
+                }
+                path.reverse();
+                return path;
+            }
+            if component.contains(next) && !parents.contains_key(next) {
+                parents.insert(next, node);
+                queue.push_back(next);
+            }
+        }
+    }
+    vec![start.to_string()]
+}
+
+/// Tarjan's strongly connected components.
+///
+/// https://en.wikipedia.org/wiki/Tarjan%27s_strongly_connected_components_algorithm
+struct StronglyConnected<'a> {
+    edges: &'a BTreeMap<String, BTreeSet<String>>,
+    indices: HashMap<&'a str, usize>,
+    low_links: HashMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: HashSet<&'a str>,
+    components: Vec<Vec<&'a str>>,
+}
+
+impl<'a> StronglyConnected<'a> {
+    fn new(edges: &'a BTreeMap<String, BTreeSet<String>>) -> StronglyConnected<'a> {
+        StronglyConnected {
+            edges,
+            indices: HashMap::new(),
+            low_links: HashMap::new(),
+            stack: vec![],
+            on_stack: HashSet::new(),
+            components: vec![],
+        }
+    }
+
+    fn visit(&mut self, node: &'a str) {
+        let index = self.indices.len();
+        self.indices.insert(node, index);
+        self.low_links.insert(node, index);
+        self.stack.push(node);
+        self.on_stack.insert(node);
+        for next in self.edges.get(node).into_iter().flatten().map(String::as_str) {
+            if !self.indices.contains_key(next) {
+                self.visit(next);
+                let low_link = self.low_links[node].min(self.low_links[next]);
+                self.low_links.insert(node, low_link);
+            } else if self.on_stack.contains(next) {
+                let low_link = self.low_links[node].min(self.indices[next]);
+                self.low_links.insert(node, low_link);
+            }
+        }
+        if self.low_links[node] == index {
+            let mut component = vec![];
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use DependencyKind::{Dev, Normal};
+
+    const A: &str = "rust/a:1.0.0";
+    const B: &str = "rust/b:1.0.0";
+    const C: &str = "rust/c:1.0.0";
+    const D: &str = "rust/d:1.0.0";
+    const E: &str = "rust/e:1.0.0";
+
+    async fn walk(store: &MemoryStore, rebuild_kinds: &[DependencyKind], built_together: &[&str]) -> ConsumerGraph {
+        let root = PackageKey::from_fq_key(A).unwrap();
+        let consumers = store.get_record(&root).await.unwrap().unwrap().consumers;
+        let built_together: HashSet<String> = built_together.iter().map(|name| name.to_string()).collect();
+        ConsumerGraph::walk(store, &root, &consumers, &rebuild_kinds.iter().copied().collect(), &built_together).await.unwrap()
+    }
+
+    fn waves(graph: &ConsumerGraph) -> Vec<Vec<&str>> {
+        graph.waves().iter().map(|wave| wave.iter().map(|consumer| match consumer.as_str() {
+            B => "b", C => "c", D => "d", E => "e", other => panic!("Unexpected consumer {}", other),
+        }).collect()).collect()
+    }
+
+    #[tokio::test]
+    async fn orders_consumers_after_their_dependencies() {
+        let store = MemoryStore::new();
+        store.track(A, "a", &[]).await;
+        store.track(B, "b", &[(A, Normal)]).await;
+        store.track(C, "c", &[(A, Normal), (B, Normal)]).await;
+        store.track(D, "d", &[(C, Normal)]).await;
+        store.track(E, "e", &[(A, Normal)]).await;
+        let graph = walk(&store, &DependencyKind::ALL, &[]).await;
+        assert_eq!(graph.cycle(), None);
+        assert_eq!(waves(&graph), [vec!["b", "e"], vec!["c"], vec!["d"]]);
+        assert_eq!([B, C, D, E].map(|consumer| graph.upstream(consumer)), [Some(A), Some(A), Some(C), Some(A)]);
+    }
+
+    #[tokio::test]
+    async fn only_follows_rebuild_kinds() {
+        let store = MemoryStore::new();
+        store.track(A, "a", &[]).await;
+        store.track(B, "b", &[(A, Normal)]).await;
+        store.track(C, "c", &[(A, Dev)]).await;
+        let graph = walk(&store, &[Normal], &[]).await;
+        assert_eq!(waves(&graph), [vec!["b"]]);
+        assert!(graph.record(C).is_none());
+    }
+
+    #[tokio::test]
+    async fn expands_a_consumer_reached_after_a_skipped_edge() {
+        // `c` is looked up through its dev-dependency on `a` first, but only reached through `b`.
+        let store = MemoryStore::new();
+        store.track(A, "a", &[]).await;
+        store.track(B, "b", &[(A, Normal)]).await;
+        store.track(C, "c", &[(A, Dev), (B, Normal)]).await;
+        store.track(D, "d", &[(C, Normal)]).await;
+        let graph = walk(&store, &[Normal], &[]).await;
+        assert_eq!(waves(&graph), [vec!["b"], vec!["c"], vec!["d"]]);
+        assert_eq!(graph.upstream(C), Some(B));
+    }
+
+    #[tokio::test]
+    async fn skips_consumers_built_together() {
+        let store = MemoryStore::new();
+        store.track(A, "a", &[]).await;
+        store.track(B, "b", &[(A, Normal)]).await;
+        store.track(C, "c", &[(B, Normal)]).await;
+        let graph = walk(&store, &DependencyKind::ALL, &["rust/b"]).await;
+        assert!(graph.waves().is_empty());
+    }
+
+    #[tokio::test]
+    async fn leaves_cycles_and_their_consumers_out_of_the_waves() {
+        let store = MemoryStore::new();
+        store.track(A, "a", &[]).await;
+        store.track(C, "c", &[]).await;
+        store.track(B, "b", &[(A, Normal), (C, Normal)]).await;
+        store.track(C, "c", &[(B, Normal)]).await;
+        store.track(D, "d", &[(C, Normal)]).await;
+        store.track(E, "e", &[(A, Normal)]).await;
+        let graph = walk(&store, &DependencyKind::ALL, &[]).await;
+        assert_eq!(graph.cycle().unwrap(), [B, C, B]);
+        assert_eq!(waves(&graph), [vec!["e"]]);
+    }
+
+    #[tokio::test]
+    async fn finds_cycles_among_every_record() {
+        let store = MemoryStore::new();
+        store.track(A, "a", &[]).await;
+        store.track(B, "b", &[(A, Dev)]).await;
+        store.track(A, "a", &[(B, Normal)]).await;
+        store.track(C, "c", &[]).await;
+        store.track(C, "c", &[(C, Normal)]).await;
+        let records = store.list_records().await.unwrap();
+        assert_eq!(find_cycles(&records, &BTreeSet::from(DependencyKind::ALL)), [vec![A, B, A], vec![C, C]]);
+        assert_eq!(find_cycles(&records, &BTreeSet::from([Normal])), [vec![C, C]]);
+    }
+
+    async fn package_graph() -> PackageGraph {
+        let store = MemoryStore::new();
+        store.track(A, "a-project", &[]).await;
+        store.track(B, "b-project", &[(A, Normal)]).await;
+        store.track(C, "c-project", &[(B, Dev), ("rust/untracked:0.1.0", Normal)]).await;
+        store.track(D, "d-project", &[]).await;
+        PackageGraph::from_records(store.list_records().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn subtree_keeps_the_root_and_its_consumers() {
+        let graph = package_graph().await.subtree(&PackageKey::from_fq_key(B).unwrap()).unwrap();
+        assert_eq!(graph.nodes.keys().collect::<Vec<_>>(), [B, C]);
+        let missing = package_graph().await.subtree(&PackageKey::from_fq_key("rust/missing:1.0.0").unwrap());
+        assert!(matches!(missing, Err(Error::NotTracked(_))));
+    }
+
+    #[tokio::test]
+    async fn draws_dot() {
+        assert_eq!(package_graph().await.to_dot(), r#"digraph packages {
+    rankdir=LR;
+    node [shape=box];
+    "rust/a:1.0.0" [label="rust/a:1.0.0\na-project"];
+    "rust/b:1.0.0" [label="rust/b:1.0.0\nb-project"];
+    "rust/c:1.0.0" [label="rust/c:1.0.0\nc-project"];
+    "rust/d:1.0.0" [label="rust/d:1.0.0\nd-project"];
+    "rust/untracked:0.1.0" [label="rust/untracked:0.1.0"];
+    "rust/a:1.0.0" -> "rust/b:1.0.0";
+    "rust/b:1.0.0" -> "rust/c:1.0.0" [label="dev"];
+    "rust/untracked:0.1.0" -> "rust/c:1.0.0";
+}
+"#);
+    }
+
+    #[tokio::test]
+    async fn draws_mermaid() {
+        assert_eq!(package_graph().await.to_mermaid(), r#"flowchart LR
+    n0["rust/a:1.0.0<br/>a-project"]
+    n1["rust/b:1.0.0<br/>b-project"]
+    n2["rust/c:1.0.0<br/>c-project"]
+    n3["rust/d:1.0.0<br/>d-project"]
+    n4["rust/untracked:0.1.0"]
+    n0 --> n1
+    n1 -->|dev| n2
+    n4 --> n2
+"#);
+    }
+}
@@ -0,0 +1,58 @@
+//! Tracks which packages consume which, and the builds that produce them, so that consumers can
+//! be rebuilt when one of their dependencies changes.
+//!
+//! The `cb-project-metadata-updater` binary is a thin command line wrapper over this crate. To do
+//! the same from another service, open a [`store::MetadataStore`], pick the
+//! [`trigger::BuildTriggers`] that start rebuilds and hand both to a [`CrateMetadataUpdater`]:
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), cb_project_metadata_updater::Error> {
+//! use std::sync::Arc;
+//! use cb_project_metadata_updater::{CrateMetadataUpdater, PackageKey};
+//! use cb_project_metadata_updater::store::SqliteStore;
+//! use cb_project_metadata_updater::trigger::BuildTriggers;
+//!
+//! let config = aws_config::load_from_env().await;
+//! let store = Arc::new(SqliteStore::open("metadata.sqlite")?);
+//! let updater = CrateMetadataUpdater::new(store, BuildTriggers::standard(&config));
+//!
+//! let pkg_key = PackageKey::from_fq_key("rust/my-crate:0.1.0")?;
+//! if let Some(record) = updater.get_package(&pkg_key).await? {
+//!     println!("{}", record);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod cargo_lock;
+pub mod crate_helper;
+pub mod dry_run;
+pub mod error;
+pub mod graph;
+pub mod manifest;
+pub mod metadata_updater;
+pub mod package_key;
+pub mod store;
+pub mod trigger;
+pub mod workspace;
+
+pub use crate_helper::CrateHelper;
+pub use error::Error;
+pub use manifest::ManifestReader;
+pub use metadata_updater::{BuildDetails, CrateMetadataUpdater, VersionMatching, WaitPolicy};
+pub use package_key::PackageKey;
+
+/// Writes `files` (relative path and contents) into a fresh directory named after `name` under the
+/// system's temp directory, and returns the directory.
+#[cfg(test)]
+pub(crate) fn temp_project(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("cb-project-metadata-updater-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    dir
+}
@@ -1,70 +1,447 @@
-mod crate_helper;
-mod metadata_updater;
-
-use std::env;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use aws_config::default_provider::credentials::DefaultCredentialsChain;
 use aws_config::meta::region::RegionProviderChain;
-use crate::crate_helper::CrateHelper;
-use crate::metadata_updater::{BuildDetails, CrateMetadataUpdater};
+use aws_sdk_dynamodb::Region;
+use clap::{ArgEnum, Args, Parser, Subcommand};
+use cb_project_metadata_updater::{BuildDetails, CrateMetadataUpdater, Error, PackageKey, VersionMatching, WaitPolicy};
+use cb_project_metadata_updater::manifest::{self, DependencyKind, Ecosystem, GitSource};
+use cb_project_metadata_updater::dry_run::{DryRunStore, Plan, PlannedRebuild};
+use cb_project_metadata_updater::package_key::normalize_git_ref;
+use cb_project_metadata_updater::store::{DynamoDbStore, MemoryStore, MetadataStore, SqliteStore};
+use cb_project_metadata_updater::trigger::{BuildTarget, BuildTriggers, RecordingTrigger, TriggerKind, ENV_SKIP_CONSUMER_REBUILDS};
 
 const ENV_CODEBUILD_BUILD_ID: &str = "CODEBUILD_BUILD_ID";
+const ENV_CODEBUILD_SOURCE_REPO_URL: &str = "CODEBUILD_SOURCE_REPO_URL";
+const ENV_CODEBUILD_WEBHOOK_HEAD_REF: &str = "CODEBUILD_WEBHOOK_HEAD_REF";
 const ENV_PKG_METADATA_TABLE: &str = "PKG_METADATA_TABLE";
+const ENV_PKG_METADATA_DATABASE: &str = "PKG_METADATA_DATABASE";
+
+/// Where manifests are detected when none is given.
+const BUILD_DIR: &str = ".";
+const DEFAULT_REGION: &str = "us-west-2";
+const DEFAULT_GIT_BRANCH: &str = "main";
+
+#[derive(Parser)]
+#[clap(version, about)]
+struct Cli {
+    /// AWS profile to load credentials from.
+    #[clap(long, global = true)]
+    profile: Option<String>,
+
+    /// AWS region. Falls back to the default region provider chain, then us-west-2.
+    #[clap(long, global = true)]
+    region: Option<String>,
+
+    /// Where package metadata is stored. The memory store starts empty and is discarded on exit,
+    /// which is useful for exercising a manifest without touching a real table.
+    #[clap(long, global = true, arg_enum, default_value = "dynamodb")]
+    store: StoreKind,
+
+    /// Name of the package metadata table.
+    #[clap(long, global = true, env = ENV_PKG_METADATA_TABLE)]
+    table: Option<String>,
+
+    /// Path to the SQLite database used by the sqlite store. Created if it doesn't exist.
+    #[clap(long, global = true, env = ENV_PKG_METADATA_DATABASE)]
+    database: Option<String>,
+
+    /// Print the writes and rebuilds a command would make instead of making them.
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    /// Kinds of dependency whose changes rebuild their consumers, comma separated: normal, dev,
+    /// build, target and peer.
+    #[clap(long, global = true, use_delimiter = true, default_value = "normal,dev,build,target,peer")]
+    rebuild_kinds: Vec<DependencyKind>,
+
+    /// Also rebuild consumers of consumers, in waves so that each package is rebuilt after the
+    /// packages it depends on. Implies --wait, and tells the rebuilds not to rebuild their own
+    /// consumers.
+    #[clap(long, global = true)]
+    transitive: bool,
+
+    /// Wait for started rebuilds to finish, and for each wave of --transitive rebuilds to finish
+    /// before starting the next. Exits with an error if any of them fail.
+    #[clap(long, global = true)]
+    wait: bool,
+
+    /// Seconds that --wait waits for all of the rebuilds together before giving up.
+    #[clap(long, global = true, default_value = "3600")]
+    wait_timeout: u64,
+
+    /// Seconds between checks of the rebuilds' statuses while waiting.
+    #[clap(long, global = true, default_value = "30")]
+    poll_interval: u64,
+
+    /// Don't start any more waves once a rebuild in a wave fails.
+    #[clap(long, global = true)]
+    abort_on_failure: bool,
+
+    /// Format of the plan printed by --dry-run.
+    #[clap(long, global = true, arg_enum, default_value = "human")]
+    plan_format: PlanFormat,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(ArgEnum, Clone)]
+enum StoreKind {
+    Dynamodb,
+    Sqlite,
+    Memory,
+}
+
+#[derive(ArgEnum, Clone)]
+enum PlanFormat {
+    Human,
+    Json,
+}
+
+#[derive(ArgEnum, Clone)]
+enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Register a package and its tracked dependencies, then rebuild its consumers.
+    Register {
+        /// Path to the package's manifest. Defaults to every manifest detected in the current
+        /// directory.
+        #[clap(long)]
+        manifest: Option<String>,
+
+        /// Only detect manifests of these ecosystems, comma separated: cargo, npm, maven, python,
+        /// go or dotnet.
+        #[clap(long = "ecosystem", use_delimiter = true)]
+        ecosystems: Vec<Ecosystem>,
+
+        /// Version of the package, for manifests that don't have one (go.mod). A git tag such as
+        /// "v1.2.3" or "refs/tags/v1.2.3" works. Defaults to the VERSION file beside the manifest.
+        #[clap(long)]
+        package_version: Option<String>,
+
+        /// CodeBuild build id of the form "ProjectName:UUID".
+        #[clap(long, env = ENV_CODEBUILD_BUILD_ID)]
+        build_id: Option<String>,
+
+        /// How consumers start a rebuild of this package: codebuild, command, webhook or noop.
+        #[clap(long, default_value = "codebuild")]
+        trigger: TriggerKind,
+
+        /// What the trigger fires: a CodeBuild project name, a shell command or a webhook URL.
+        /// Defaults to the project in --build-id for codebuild triggers.
+        #[clap(long)]
+        build_target: Option<String>,
+
+        /// Which tracked versions of a dependency to link to when its requirement matches more
+        /// than one and there's no Cargo.lock: all or highest.
+        #[clap(long, default_value = "all")]
+        link_versions: VersionMatching,
+
+        /// URL of the repository being built. Consumers depending on it through git are rebuilt
+        /// when it's registered.
+        #[clap(long, env = ENV_CODEBUILD_SOURCE_REPO_URL)]
+        git_url: Option<String>,
+
+        /// Branch or tag being built (e.g. "main" or "refs/heads/main"). Defaults to the default
+        /// branch.
+        #[clap(long, env = ENV_CODEBUILD_WEBHOOK_HEAD_REF)]
+        git_ref: Option<String>,
+
+        /// The repository's default branch. Builds of it are also registered for git
+        /// dependencies that don't name a branch, tag or revision.
+        #[clap(long, default_value = DEFAULT_GIT_BRANCH)]
+        git_default_branch: String,
+
+        /// Only track dependencies from this registry (its name, index URL or "crates-io").
+        /// Repeat for more than one. Dependencies from every registry are tracked if not given.
+        #[clap(long = "tracked-registry")]
+        tracked_registries: Vec<String>,
+
+        /// Don't rebuild the consumers of the registered packages. Set by --transitive rebuilds,
+        /// which rebuild those consumers themselves.
+        #[clap(long, env = ENV_SKIP_CONSUMER_REBUILDS)]
+        skip_consumer_rebuilds: bool,
+    },
+    /// Show the stored record for a package.
+    Show(PackageArgs),
+    /// List the consumers of a package.
+    Consumers(PackageArgs),
+    /// List the tracked dependencies of a package.
+    Dependencies(PackageArgs),
+    /// List every tracked version of a package.
+    Versions(PackageArgs),
+    /// Rebuild the consumers of a package without re-registering it.
+    Rebuild(PackageArgs),
+    /// Remove a package's record and detach it from its dependencies and consumers.
+    Forget(PackageArgs),
+    /// List cycles of consumers among every tracked package, one per line, following only the
+    /// --rebuild-kinds dependencies.
+    Cycles,
+    /// Print the graph of tracked packages, with edges from each dependency to its consumers.
+    Graph {
+        /// Output format: Graphviz DOT or a Mermaid flowchart.
+        #[clap(long, arg_enum, default_value = "dot")]
+        format: GraphFormat,
+
+        /// Only include this package (e.g. "rust/my-crate:0.1.0") and the packages that consume
+        /// it, directly or through other consumers.
+        #[clap(long)]
+        root: Option<String>,
+    },
+}
+
+#[derive(Args)]
+struct PackageArgs {
+    /// Fully qualified package key (e.g. "rust/my-crate:0.1.0"). Defaults to the package
+    /// described by --manifest.
+    package: Option<String>,
+
+    /// Path to the manifest used when no package key is given. Defaults to the one manifest
+    /// detected in the current directory.
+    #[clap(long)]
+    manifest: Option<String>,
+
+    /// Only detect manifests of these ecosystems, comma separated.
+    #[clap(long = "ecosystem", use_delimiter = true)]
+    ecosystems: Vec<Ecosystem>,
+
+    /// Version of the package described by --manifest, if the manifest doesn't have one (go.mod).
+    #[clap(long)]
+    package_version: Option<String>,
+}
+
+impl PackageArgs {
+    fn package_key(&self) -> Result<PackageKey, Error> {
+        match &self.package {
+            Some(fq_key) => PackageKey::from_fq_key(fq_key),
+            None => {
+                let manifests = find_manifests(self.manifest.clone(), &self.ecosystems)?;
+                match manifests.as_slice() {
+                    [manifest] => Ok(PackageKey::from(manifest::read_package(manifest, self.package_version.as_deref())?.as_ref())),
+                    _ => {
+                        let paths: Vec<String> = manifests.iter().map(|manifest| manifest.display().to_string()).collect();
+                        Err(Error::MissingConfig(format!("Found several manifests ({}). Pass --manifest or --ecosystem to pick one.", paths.join(", "))))
+                    }
+                }
+            }
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    // TODO: Add command-line arguments
-    // https://doc.rust-lang.org/book/ch12-01-accepting-command-line-arguments.html
-    match update_metadata().await {
+    let cli = Cli::parse();
+    match run(cli).await {
         Ok(_) => (),
         Err(err) => {
-            eprintln!("ERROR: {}", err.msg);
-            std::process::exit(1)
+            eprintln!("ERROR: {}", err);
+            std::process::exit(err.exit_code())
         }
     }
 }
 
-async fn update_metadata() -> Result<(), crate_helper::Error> {
-    let build_details = match get_build_details() {
-        Ok(build_details) => build_details,
-        Err(err) => return Err(err)
+async fn run(cli: Cli) -> Result<(), Error> {
+    let config = load_config(cli.profile, cli.region).await;
+    let store = open_store(&cli.store, cli.table, cli.database, &config)?;
+    let rebuild_kinds: BTreeSet<DependencyKind> = cli.rebuild_kinds.into_iter().collect();
+    let wait_policy = (cli.wait || cli.transitive).then(|| WaitPolicy {
+        timeout: Duration::from_secs(cli.wait_timeout),
+        poll_interval: Duration::from_secs(cli.poll_interval),
+        abort_on_failure: cli.abort_on_failure,
+    });
+    if !cli.dry_run {
+        let updater = CrateMetadataUpdater::new(store, BuildTriggers::standard(&config))
+            .with_rebuild_kinds(rebuild_kinds)
+            .with_transitive(cli.transitive)
+            .with_wait_policy(wait_policy);
+        return run_command(cli.command, updater).await;
+    }
+
+    let dry_run_store = Arc::new(DryRunStore::new(store));
+    let recorder = Arc::new(RecordingTrigger::new());
+    let updater = CrateMetadataUpdater::new(dry_run_store.clone(), BuildTriggers::all(recorder.clone()))
+        .with_rebuild_kinds(rebuild_kinds)
+        .with_transitive(cli.transitive);
+    run_command(cli.command, updater).await?;
+    let plan = Plan {
+        writes: dry_run_store.planned_writes(),
+        rebuilds: recorder.recorded().into_iter().map(PlannedRebuild::from).collect(),
     };
+    match cli.plan_format {
+        PlanFormat::Human => print!("{}", plan),
+        PlanFormat::Json => match serde_json::to_string_pretty(&plan) {
+            Ok(json) => println!("{}", json),
+            Err(err) => return Err(Error::Output(Box::new(err)))
+        },
+    }
+    Ok(())
+}
+
+async fn run_command(command: Command, updater: CrateMetadataUpdater) -> Result<(), Error> {
+    match command {
+        Command::Register { manifest, ecosystems, package_version, build_id, trigger, build_target, link_versions, git_url, git_ref, git_default_branch, tracked_registries, skip_consumer_rebuilds } => {
+            let sources = git_sources(git_url, git_ref, git_default_branch);
+            let build_details = get_build_details(build_id, trigger, build_target, sources)?;
+            updater.with_version_matching(link_versions)
+                .with_tracked_registries(tracked_registries.into_iter().collect())
+                .with_package_version(package_version)
+                .with_skip_consumer_rebuilds(skip_consumer_rebuilds)
+                .update_metadata(build_details, &find_manifests(manifest, &ecosystems)?).await
+        },
+        Command::Show(args) => {
+            let pkg_key = args.package_key()?;
+            match updater.get_package(&pkg_key).await? {
+                Some(record) => {
+                    print!("{}", record);
+                    Ok(())
+                },
+                None => Err(Error::NotTracked(pkg_key.to_fq_key()))
+            }
+        },
+        Command::Consumers(args) => {
+            let pkg_key = args.package_key()?;
+            if let Some(record) = updater.get_package(&pkg_key).await? {
+                record.consumers.iter().for_each(|consumer| println!("{}", consumer));
+            }
+            Ok(())
+        },
+        Command::Dependencies(args) => {
+            let pkg_key = args.package_key()?;
+            if let Some(record) = updater.get_package(&pkg_key).await? {
+                record.dependencies.iter().for_each(|dependency| println!("{}", dependency));
+            }
+            Ok(())
+        },
+        Command::Versions(args) => {
+            let pkg_key = args.package_key()?;
+            for version in updater.list_versions(&pkg_key).await? {
+                println!("{}", version);
+            }
+            Ok(())
+        },
+        Command::Rebuild(args) => updater.rebuild_consumers(&args.package_key()?).await,
+        Command::Forget(args) => updater.forget_package(&args.package_key()?).await,
+        Command::Cycles => {
+            for cycle in updater.find_cycles().await? {
+                println!("{}", cycle.join(" -> "));
+            }
+            Ok(())
+        },
+        Command::Graph { format, root } => {
+            let mut graph = updater.package_graph().await?;
+            if let Some(root) = root {
+                graph = graph.subtree(&PackageKey::from_fq_key(&root)?)?;
+            }
+            match format {
+                GraphFormat::Dot => print!("{}", graph.to_dot()),
+                GraphFormat::Mermaid => print!("{}", graph.to_mermaid()),
+            }
+            Ok(())
+        },
+    }
+}
+
+/// The manifest given on the command line, or otherwise those detected in the build directory.
+fn find_manifests(manifest: Option<String>, ecosystems: &[Ecosystem]) -> Result<Vec<PathBuf>, Error> {
+    match manifest {
+        Some(manifest) => Ok(vec![PathBuf::from(manifest)]),
+        None => manifest::detect_manifests(Path::new(BUILD_DIR), ecosystems),
+    }
+}
+
+fn open_store(kind: &StoreKind, table: Option<String>, database: Option<String>, config: &aws_config::Config) -> Result<Arc<dyn MetadataStore>, Error> {
+    match kind {
+        StoreKind::Dynamodb => {
+            let table_name = match table {
+                Some(table_name) => table_name,
+                None => return Err(Error::MissingConfig(format!("Unable to determine Package Metadata table name. Pass --table or set the {} env variable", ENV_PKG_METADATA_TABLE)))
+            };
+            eprintln!("Using {} table.", table_name);
+            Ok(Arc::new(DynamoDbStore::new(config, table_name)))
+        },
+        StoreKind::Sqlite => {
+            let database = match database {
+                Some(database) => database,
+                None => return Err(Error::MissingConfig(format!("Unable to determine Package Metadata database path. Pass --database or set the {} env variable", ENV_PKG_METADATA_DATABASE)))
+            };
+            eprintln!("Using {} database.", database);
+            Ok(Arc::new(SqliteStore::open(database)?))
+        },
+        StoreKind::Memory => {
+            eprintln!("Using in-memory store. Nothing will be persisted.");
+            Ok(Arc::new(MemoryStore::new()))
+        },
+    }
+}
 
-    // This is a hack for quick support for local profiles. Arguments should be properly fleshed out
-    let mut credential_chain =
-        DefaultCredentialsChain::builder()
-            .region(RegionProviderChain::default_provider().or_else("us-west-2"));
-    let args: Vec<String> = env::args().collect();
-    if let Some(profile_name) = args.get(1) {
+async fn load_config(profile: Option<String>, region: Option<String>) -> aws_config::Config {
+    let mut credential_chain = DefaultCredentialsChain::builder()
+        .region(region_provider(&region));
+    if let Some(profile_name) = &profile {
         eprintln!("Using AWS profile \"{}\"", profile_name);
         credential_chain = credential_chain.profile_name(profile_name);
     }
-    let config =
-        aws_config::from_env()
-            .credentials_provider(credential_chain.build().await).load().await;
-    match std::env::var(ENV_PKG_METADATA_TABLE) {
-        Ok(table_value) => {
-            eprintln!("Writing changes to {} table.", table_value);
-            let updater = CrateMetadataUpdater::new(&config, table_value);
-            updater.update_metadata(build_details, String::from("./Cargo.toml")).await
-        },
-        Err(_) => Err(crate_helper::Error::with_msg(format!("Unable to determine Package Metadata table name from {} env variable", ENV_PKG_METADATA_TABLE)))
+    aws_config::from_env()
+        .region(region_provider(&region))
+        .credentials_provider(credential_chain.build().await)
+        .load().await
+}
+
+fn region_provider(region: &Option<String>) -> RegionProviderChain {
+    match region {
+        Some(region) => RegionProviderChain::first_try(Region::new(region.clone()))
+            .or_default_provider()
+            .or_else(DEFAULT_REGION),
+        None => RegionProviderChain::default_provider().or_else(DEFAULT_REGION),
     }
 }
 
-fn get_build_details() -> Result<BuildDetails, crate_helper::Error> {
-    match env::var(ENV_CODEBUILD_BUILD_ID) {
-        Ok(build_id) => {
-            let parts: Vec<&str> = build_id.split(":").collect();
+fn get_build_details(build_id: Option<String>, kind: TriggerKind, build_target: Option<String>, sources: Vec<GitSource>) -> Result<BuildDetails, Error> {
+    if let Some(target) = build_target {
+        return Ok(BuildDetails {
+            build_target: BuildTarget { kind, target },
+            sources,
+        });
+    }
+    if kind != TriggerKind::CodeBuild {
+        return Err(Error::MissingConfig(format!("--build-target is required for {} triggers", kind)));
+    }
+    match build_id {
+        Some(build_id) => {
+            let parts: Vec<&str> = build_id.split(':').collect();
             let build_project_name = String::from(
-                *parts.get(0)
+                *parts.first()
                     .expect("Expected string of pattern \"ProjectName:UUID\"")
             );
             Ok(BuildDetails {
-                build_project_name
+                build_target: BuildTarget { kind, target: build_project_name },
+                sources,
             })
         },
-        Err(_) => Err(crate_helper::Error {
-            msg: format!("Didn't find {} env var", ENV_CODEBUILD_BUILD_ID)
-        })
+        None => Err(Error::MissingConfig(format!("No build id given. Pass --build-id or set the {} env var", ENV_CODEBUILD_BUILD_ID)))
     }
-}
\ No newline at end of file
+}
+
+fn git_sources(url: Option<String>, reference: Option<String>, default_branch: String) -> Vec<GitSource> {
+    let url = match url {
+        Some(url) => url,
+        None => return vec![]
+    };
+    // Builds that weren't started by a push are of the default branch.
+    let reference = reference.map(|reference| normalize_git_ref(&reference)).unwrap_or_else(|| default_branch.clone());
+    if reference == default_branch {
+        vec![
+            GitSource { url: url.clone(), reference: Some(reference) },
+            GitSource { url, reference: None },
+        ]
+    } else {
+        vec![GitSource { url, reference: Some(reference) }]
+    }
+}
@@ -0,0 +1,504 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use roxmltree::{Document, Node};
+use serde::Deserialize;
+use crate::error::Error;
+use crate::manifest::{manifest_dir, parse_interval_ranges, Dependency, DependencyKind, ManifestReader, VersionRange};
+use crate::package_key::NUGET_BUILD_SYSTEM;
+
+const BUILD_PROPS_FILE_NAME: &str = "Directory.Build.props";
+const PACKAGES_PROPS_FILE_NAME: &str = "Directory.Packages.props";
+const LOCK_FILE_NAME: &str = "packages.lock.json";
+/// The version the .NET SDK gives packages that don't set one.
+const DEFAULT_VERSION: &str = "1.0.0";
+/// Properties that refer to other properties are substituted at most this many times.
+const MAX_SUBSTITUTIONS: usize = 16;
+
+const ITEM_PACKAGE_REFERENCE: &str = "PackageReference";
+const ITEM_PROJECT_REFERENCE: &str = "ProjectReference";
+const ITEM_PACKAGE_VERSION: &str = "PackageVersion";
+
+/// The properties and items of one MSBuild file, before property substitution.
+struct MsBuildFile {
+    properties: Vec<(String, String)>,
+    items: Vec<Item>,
+}
+
+struct Item {
+    item_type: String,
+    include: String,
+    version: Option<String>,
+    /// Overrides the central version from Directory.Packages.props.
+    version_override: Option<String>,
+    private_assets: Option<String>,
+    conditional: bool,
+}
+
+/// https://learn.microsoft.com/en-us/nuget/consume-packages/package-references-in-project-files#locking-dependencies
+#[derive(Deserialize)]
+struct LockFile {
+    /// Resolved packages by target framework, then by id.
+    #[serde(default)]
+    dependencies: BTreeMap<String, BTreeMap<String, LockedPackage>>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    resolved: Option<String>,
+}
+
+/// A project file with its properties evaluated.
+struct Project {
+    path: PathBuf,
+    file: MsBuildFile,
+    properties: HashMap<String, String>,
+}
+
+/// A NuGet package's id, version and package references, read from its SDK-style project file.
+pub struct NugetPackage {
+    name: String,
+    version: String,
+    dependencies: Vec<Dependency>,
+}
+
+impl NugetPackage {
+    /// Reads the .csproj at `path`. Properties from the nearest Directory.Build.props apply, and
+    /// references without a version take theirs from the nearest Directory.Packages.props.
+    /// Versions locked in a packages.lock.json beside the project are linked exactly.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let project = Project::from_path(path.as_ref())?;
+        let project_dir = manifest_dir(&project.path);
+
+        // https://learn.microsoft.com/en-us/nuget/consume-packages/central-package-management
+        let mut central_versions: HashMap<String, String> = HashMap::new();
+        if let Some(packages_props) = find_up(&project_dir, PACKAGES_PROPS_FILE_NAME) {
+            let packages_props = MsBuildFile::from_path(&packages_props)?;
+            let mut properties = project.properties.clone();
+            properties.extend(packages_props.properties.iter().cloned());
+            for item in packages_props.items.iter().filter(|item| item.item_type == ITEM_PACKAGE_VERSION) {
+                if let Some(version) = &item.version {
+                    central_versions.insert(item.include.to_lowercase(), substitute(version, &properties));
+                }
+            }
+        }
+        let locked_versions = read_lock_file(&project_dir.join(LOCK_FILE_NAME))?;
+
+        let mut dependencies = vec![];
+        for item in &project.file.items {
+            let kind = if item.conditional {
+                DependencyKind::Target
+            } else if item.private_assets.as_deref().is_some_and(|assets| assets.eq_ignore_ascii_case("all")) {
+                // Analyzers and build tools that don't flow to the package's consumers.
+                DependencyKind::Build
+            } else {
+                DependencyKind::Normal
+            };
+            let include = substitute(&item.include, &project.properties);
+            match item.item_type.as_str() {
+                ITEM_PACKAGE_REFERENCE => {
+                    let name = include.to_lowercase();
+                    let version = item.version_override.as_ref().or(item.version.as_ref())
+                        .map(|version| substitute(version, &project.properties))
+                        .or_else(|| central_versions.get(&name).cloned());
+                    dependencies.push(Dependency {
+                        locked_version: locked_versions.get(&name).cloned(),
+                        name,
+                        version,
+                        path: None,
+                        git: None,
+                        registry: None,
+                        kind,
+                    });
+                },
+                ITEM_PROJECT_REFERENCE => {
+                    // Project references are built from whatever is at the path.
+                    let reference_path = project_dir.join(include.replace('\\', "/"));
+                    let reference = Project::from_path(&reference_path)?;
+                    dependencies.push(Dependency {
+                        name: reference.package_id(),
+                        version: None,
+                        locked_version: Some(reference.version()),
+                        path: Some(manifest_dir(&reference_path)),
+                        git: None,
+                        registry: None,
+                        kind,
+                    });
+                },
+                _ => ()
+            }
+        }
+        Ok(NugetPackage {
+            name: project.package_id(),
+            version: project.version(),
+            dependencies,
+        })
+    }
+}
+
+impl ManifestReader for NugetPackage {
+    fn build_system(&self) -> &'static str {
+        NUGET_BUILD_SYSTEM
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    fn dependencies(&self) -> &[Dependency] {
+        &self.dependencies
+    }
+
+    fn matching_versions(&self, requirement: &str, versions: &[String]) -> Option<Vec<String>> {
+        // https://learn.microsoft.com/en-us/nuget/concepts/package-versioning#version-ranges
+        let requirement = requirement.trim();
+        // A bare version is a minimum, and NuGet resolves it to the lowest version that meets it.
+        // A floating version resolves to the highest.
+        let floating = requirement.ends_with('*');
+        let lowest_only = !requirement.starts_with(['[', '(']) && !floating;
+        let ranges = if requirement.starts_with(['[', '(']) {
+            parse_interval_ranges(requirement)?
+        } else if requirement == "*" {
+            vec![VersionRange { lower: None, upper: None }]
+        } else if let Some(prefix) = requirement.strip_suffix(".*") {
+            // Floating versions: "1.2.*" is the highest 1.2 release.
+            let mut parts: Vec<u64> = vec![];
+            for part in prefix.split('.') {
+                parts.push(part.parse().ok()?);
+            }
+            let lower = parts.iter().map(u64::to_string).collect::<Vec<String>>().join(".");
+            if let Some(last) = parts.last_mut() {
+                *last += 1;
+            }
+            let upper = parts.iter().map(u64::to_string).collect::<Vec<String>>().join(".");
+            vec![VersionRange { lower: Some((lower, true)), upper: Some((upper, false)) }]
+        } else {
+            NugetVersion::parse(requirement)?;
+            vec![VersionRange { lower: Some((requirement.to_string(), true)), upper: None }]
+        };
+        // Pre-releases are only candidates if the requirement names one.
+        let allow_pre_releases = requirement.contains('-');
+        let mut matching_versions: Vec<String> = versions.iter()
+            .filter(|version| NugetVersion::parse(version)
+                .is_some_and(|version| allow_pre_releases || version.pre_release.is_empty()))
+            .filter(|version| ranges.iter().any(|range| range.contains(version, compare_versions)))
+            .cloned()
+            .collect();
+        matching_versions.sort_by(|a, b| compare_versions(a, b));
+        if lowest_only {
+            matching_versions.truncate(1);
+        } else if floating {
+            matching_versions = matching_versions.pop().into_iter().collect();
+        }
+        Some(matching_versions)
+    }
+}
+
+impl Project {
+    fn from_path(path: &Path) -> Result<Project, Error> {
+        let file = MsBuildFile::from_path(path)?;
+        // https://learn.microsoft.com/en-us/visualstudio/msbuild/customize-by-directory
+        let mut properties = HashMap::new();
+        if let Some(build_props) = find_up(&manifest_dir(path), BUILD_PROPS_FILE_NAME) {
+            properties.extend(MsBuildFile::from_path(&build_props)?.properties);
+        }
+        let project_name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        properties.insert(String::from("MSBuildProjectName"), project_name);
+        properties.extend(file.properties.iter().cloned());
+        Ok(Project {
+            path: path.to_path_buf(),
+            file,
+            properties,
+        })
+    }
+
+    /// The package id, lowercase since NuGet ids are case-insensitive. Defaults to the assembly
+    /// name, which defaults to the project file's name.
+    fn package_id(&self) -> String {
+        let id = self.property("PackageId")
+            .or_else(|| self.property("AssemblyName"))
+            .or_else(|| self.property("MSBuildProjectName"))
+            .unwrap_or_default();
+        id.to_lowercase()
+    }
+
+    /// The package version: `Version`, or `VersionPrefix` and `VersionSuffix`, normalized.
+    fn version(&self) -> String {
+        if let Some(version) = self.property("PackageVersion").or_else(|| self.property("Version")) {
+            return normalize_version(&version);
+        }
+        let prefix = self.property("VersionPrefix").unwrap_or_else(|| String::from(DEFAULT_VERSION));
+        match self.property("VersionSuffix") {
+            Some(suffix) => normalize_version(&format!("{}-{}", prefix, suffix)),
+            None => normalize_version(&prefix),
+        }
+    }
+
+    fn property(&self, name: &str) -> Option<String> {
+        self.properties.get(name)
+            .map(|value| substitute(value, &self.properties))
+            .filter(|value| !value.is_empty())
+    }
+}
+
+impl MsBuildFile {
+    fn from_path(path: &Path) -> Result<MsBuildFile, Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => return Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+        };
+        let document = match Document::parse(&contents) {
+            Ok(document) => document,
+            Err(err) => return Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+        };
+        let root = document.root_element();
+        // Later properties override earlier ones, as they do when MSBuild evaluates the file.
+        let properties = root.children()
+            .filter(|group| group.has_tag_name("PropertyGroup"))
+            .flat_map(|group| group.children().filter(|property| property.is_element()))
+            .map(|property| (property.tag_name().name().to_string(), property.text().unwrap_or("").trim().to_string()))
+            .collect();
+        let items = root.children()
+            .filter(|group| group.has_tag_name("ItemGroup"))
+            .flat_map(|group| {
+                let group_conditional = group.attribute("Condition").is_some();
+                group.children()
+                    .filter(|item| item.is_element())
+                    .filter_map(move |item| Some(Item {
+                        item_type: item.tag_name().name().to_string(),
+                        include: item.attribute("Include")?.trim().to_string(),
+                        version: metadata(item, "Version"),
+                        version_override: metadata(item, "VersionOverride"),
+                        private_assets: metadata(item, "PrivateAssets"),
+                        conditional: group_conditional || item.attribute("Condition").is_some(),
+                    }))
+            })
+            .collect();
+        Ok(MsBuildFile { properties, items })
+    }
+}
+
+/// Item metadata, which can be either an attribute or a child element.
+fn metadata(item: Node, name: &str) -> Option<String> {
+    item.attribute(name).map(String::from)
+        .or_else(|| item.children().find(|child| child.has_tag_name(name)).and_then(|child| child.text()).map(String::from))
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Reads the resolved version of each package in a packages.lock.json, keyed by lowercase id.
+/// Returns nothing if there's no lock file.
+fn read_lock_file(path: &Path) -> Result<HashMap<String, String>, Error> {
+    if !path.is_file() {
+        return Ok(HashMap::new());
+    }
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => return Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+    };
+    let lock_file: LockFile = match serde_json::from_str(&contents) {
+        Ok(lock_file) => lock_file,
+        Err(err) => return Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+    };
+    let mut locked_versions = HashMap::new();
+    for packages in lock_file.dependencies.into_values() {
+        for (id, package) in packages {
+            if let Some(resolved) = package.resolved {
+                locked_versions.entry(id.to_lowercase()).or_insert(normalize_version(&resolved));
+            }
+        }
+    }
+    Ok(locked_versions)
+}
+
+/// The nearest file named `file_name` in `dir` or above it.
+fn find_up(dir: &Path, file_name: &str) -> Option<PathBuf> {
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors().map(|dir| dir.join(file_name)).find(|path| path.is_file())
+}
+
+/// Replaces `$(Name)` with the property's value. Unknown properties are empty, as in MSBuild.
+fn substitute(value: &str, properties: &HashMap<String, String>) -> String {
+    let mut value = value.to_string();
+    for _ in 0..MAX_SUBSTITUTIONS {
+        let (start, end) = match value.find("$(").and_then(|start| value[start..].find(')').map(|end| (start, start + end))) {
+            Some(bounds) => bounds,
+            None => break
+        };
+        let property = properties.get(&value[start + 2..end]).cloned().unwrap_or_default();
+        value.replace_range(start..=end, &property);
+    }
+    value
+}
+
+/// A NuGet version: up to four numbers, then an optional pre-release label. Build metadata is
+/// ignored.
+///
+/// https://learn.microsoft.com/en-us/nuget/concepts/package-versioning#normalized-version-numbers
+struct NugetVersion {
+    numbers: [u64; 4],
+    pre_release: Vec<String>,
+}
+
+impl NugetVersion {
+    fn parse(version: &str) -> Option<NugetVersion> {
+        let version = version.trim().split('+').next()?;
+        let (numbers, pre_release) = match version.split_once('-') {
+            Some((numbers, pre_release)) => (numbers, pre_release.split('.').map(str::to_lowercase).collect()),
+            None => (version, vec![]),
+        };
+        let mut parsed = [0; 4];
+        let parts: Vec<&str> = numbers.split('.').collect();
+        if parts.len() > 4 {
+            return None;
+        }
+        for (index, part) in parts.iter().enumerate() {
+            parsed[index] = part.parse().ok()?;
+        }
+        Some(NugetVersion { numbers: parsed, pre_release })
+    }
+}
+
+/// Writes a version the way NuGet normalizes it, so that "1.2" and "1.2.0" are the same key: at
+/// least three numbers, a fourth only if it isn't zero, and no build metadata. Versions that
+/// don't parse are left as they are.
+fn normalize_version(version: &str) -> String {
+    let parsed = match NugetVersion::parse(version) {
+        Some(parsed) => parsed,
+        None => return version.trim().to_string(),
+    };
+    let parts = if parsed.numbers[3] == 0 { 3 } else { 4 };
+    let numbers: Vec<String> = parsed.numbers[..parts].iter().map(u64::to_string).collect();
+    let version = version.trim().split('+').next().unwrap_or("");
+    match version.split_once('-') {
+        Some((_, pre_release)) => format!("{}-{}", numbers.join("."), pre_release),
+        None => numbers.join("."),
+    }
+}
+
+/// Orders versions as NuGet does: by number, then releases after pre-releases, then pre-release
+/// labels part by part with numeric parts before text ones.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a, b) = match (NugetVersion::parse(a), NugetVersion::parse(b)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return a.cmp(b),
+    };
+    a.numbers.cmp(&b.numbers).then_with(|| match (a.pre_release.is_empty(), b.pre_release.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => {
+            for (a, b) in a.pre_release.iter().zip(&b.pre_release) {
+                let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    (Ok(_), Err(_)) => Ordering::Less,
+                    (Err(_), Ok(_)) => Ordering::Greater,
+                    (Err(_), Err(_)) => a.cmp(b),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            a.pre_release.len().cmp(&b.pre_release.len())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_project;
+
+    fn matching(requirement: &str, versions: &[&str]) -> Option<Vec<String>> {
+        let package = NugetPackage { name: String::from("consumer"), version: String::from(DEFAULT_VERSION), dependencies: vec![] };
+        let versions: Vec<String> = versions.iter().map(|version| version.to_string()).collect();
+        package.matching_versions(requirement, &versions)
+    }
+
+    #[test]
+    fn versions_are_ordered_like_nuget() {
+        let ordered = ["1.0.0-alpha", "1.0.0-alpha.2", "1.0.0-alpha.10", "1.0.0-beta", "1.0.0", "1.0.0.1", "1.2"];
+        for pair in ordered.windows(2) {
+            assert_eq!(compare_versions(pair[0], pair[1]), Ordering::Less, "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(compare_versions("1.2", "1.2.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.2.0+build5", "1.2.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn bare_versions_link_the_lowest_match() {
+        let versions = ["1.1.0", "1.2.0", "1.3.0", "2.0.0"];
+        assert_eq!(matching("1.2", &versions).unwrap(), ["1.2.0"]);
+        assert_eq!(matching("1.2.5", &versions).unwrap(), ["1.3.0"]);
+        assert_eq!(matching("3.0", &versions).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn ranges_link_every_match() {
+        let versions = ["1.0.0", "1.2.0", "1.2.7", "1.3.0-beta", "2.0.0"];
+        assert_eq!(matching("[1.0,2.0)", &versions).unwrap(), ["1.0.0", "1.2.0", "1.2.7"]);
+        assert_eq!(matching("(1.0,]", &versions).unwrap(), ["1.2.0", "1.2.7", "2.0.0"]);
+        assert_eq!(matching("[1.2]", &versions).unwrap(), ["1.2.0"]);
+    }
+
+    #[test]
+    fn floating_versions_link_the_highest_match() {
+        let versions = ["1.0.0", "1.2.0", "1.2.7", "1.3.0-beta", "2.0.0"];
+        assert_eq!(matching("1.2.*", &versions).unwrap(), ["1.2.7"]);
+        assert_eq!(matching("*", &versions).unwrap(), ["2.0.0"]);
+        assert_eq!(matching("3.*", &versions).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn versions_are_normalized_to_three_numbers() {
+        assert_eq!(normalize_version("1.2"), "1.2.0");
+        assert_eq!(normalize_version("1.2.0.0"), "1.2.0");
+        assert_eq!(normalize_version("1.2.0.4"), "1.2.0.4");
+        assert_eq!(normalize_version("2-beta.1+build5"), "2.0.0-beta.1");
+        assert_eq!(normalize_version("latest"), "latest");
+    }
+
+    #[test]
+    fn unknown_requirements_are_not_understood() {
+        assert!(matching("latest", &["1.0.0"]).is_none());
+        assert!(matching("1.x.*", &["1.0.0"]).is_none());
+    }
+
+    #[test]
+    fn reads_references_with_central_versions_and_kinds() {
+        let dir = temp_project("dotnet", &[
+            ("Directory.Build.props", "<Project><PropertyGroup><Company>Acme</Company></PropertyGroup></Project>"),
+            ("Directory.Packages.props", r#"<Project>
+                <ItemGroup><PackageVersion Include="Newtonsoft.Json" Version="13.0.1" /></ItemGroup>
+            </Project>"#),
+            ("src/App/App.csproj", r#"<Project Sdk="Microsoft.NET.Sdk">
+                <PropertyGroup><PackageId>$(Company).App</PackageId><VersionPrefix>2.1.0</VersionPrefix></PropertyGroup>
+                <ItemGroup>
+                    <PackageReference Include="Newtonsoft.Json" />
+                    <PackageReference Include="StyleCop.Analyzers" Version="1.1.118" PrivateAssets="all" />
+                    <ProjectReference Include="..\Lib\Lib.csproj" />
+                </ItemGroup>
+                <ItemGroup Condition="'$(TargetFramework)' == 'net48'">
+                    <PackageReference Include="System.Memory"><Version>[4.5,5.0)</Version></PackageReference>
+                </ItemGroup>
+            </Project>"#),
+            ("src/Lib/Lib.csproj", r#"<Project Sdk="Microsoft.NET.Sdk"><PropertyGroup><Version>0.4</Version></PropertyGroup></Project>"#),
+        ]);
+        let package = NugetPackage::from_path(dir.join("src/App/App.csproj")).unwrap();
+        assert_eq!((package.name(), package.version()), (String::from("acme.app"), String::from("2.1.0")));
+        let dependencies: Vec<(&str, Option<&str>, Option<&str>, DependencyKind)> = package.dependencies().iter()
+            .map(|dependency| (dependency.name.as_str(), dependency.version.as_deref(), dependency.locked_version.as_deref(), dependency.kind))
+            .collect();
+        assert_eq!(dependencies, [
+            ("newtonsoft.json", Some("13.0.1"), None, DependencyKind::Normal),
+            ("stylecop.analyzers", Some("1.1.118"), None, DependencyKind::Build),
+            ("lib", None, Some("0.4.0"), DependencyKind::Normal),
+            ("system.memory", Some("[4.5,5.0)"), None, DependencyKind::Target),
+        ]);
+    }
+}
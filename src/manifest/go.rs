@@ -0,0 +1,269 @@
+use std::fs;
+use std::path::Path;
+use crate::error::Error;
+use crate::manifest::{manifest_dir, Dependency, DependencyKind, ManifestReader};
+use crate::package_key::{normalize_git_ref, GO_BUILD_SYSTEM};
+
+const MANIFEST_FILE_NAME: &str = "go.mod";
+const VERSION_FILE_NAME: &str = "VERSION";
+
+/// One directive of a go.mod, such as a `require` line, split into words with comments removed.
+struct Directive<'a> {
+    verb: &'a str,
+    words: Vec<&'a str>,
+}
+
+/// The module and its requirements, read from a go.mod.
+///
+/// go.mod doesn't record the module's own version, so it comes from the caller (usually the git
+/// tag being built) or a VERSION file beside go.mod.
+pub struct GoModule {
+    name: String,
+    version: String,
+    dependencies: Vec<Dependency>,
+}
+
+impl GoModule {
+    /// Reads the go.mod at `path`. `version` takes precedence over the VERSION file, and one of the
+    /// two is required.
+    pub fn from_path(path: impl AsRef<Path>, version: Option<&str>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = read_to_string(path)?;
+        let directives = parse_directives(&contents);
+        let name = module_path(path, &directives)?;
+        let manifest_dir = manifest_dir(path);
+        let version = match version {
+            Some(version) => normalize_version(version),
+            None => match version_file(&manifest_dir)? {
+                Some(version) => version,
+                None => return Err(Error::InvalidManifest {
+                    path: path.to_path_buf(),
+                    reason: format!("go.mod has no version. Pass one or add a {} file beside it", VERSION_FILE_NAME),
+                })
+            }
+        };
+
+        // https://go.dev/ref/mod#go-mod-file-require
+        // With minimal version selection, the required version is the one that's built unless a
+        // replace directive says otherwise.
+        let mut dependencies = vec![];
+        for directive in directives.iter().filter(|directive| directive.verb == "require") {
+            if let [module, version, ..] = directive.words[..] {
+                dependencies.push(Dependency {
+                    name: unquote(module),
+                    version: Some(unquote(version)),
+                    path: None,
+                    locked_version: Some(unquote(version)),
+                    git: None,
+                    registry: None,
+                    kind: DependencyKind::Normal,
+                });
+            }
+        }
+
+        // https://go.dev/ref/mod#go-mod-file-replace
+        for directive in directives.iter().filter(|directive| directive.verb == "replace") {
+            let arrow = match directive.words.iter().position(|word| *word == "=>") {
+                Some(arrow) => arrow,
+                None => continue
+            };
+            let (old, new) = directive.words.split_at(arrow);
+            let new = &new[1..];
+            let (old_module, old_version) = match old {
+                [module] => (unquote(module), None),
+                [module, version] => (unquote(module), Some(unquote(version))),
+                _ => continue
+            };
+            let replaced = dependencies.iter_mut()
+                .filter(|dependency| dependency.name == old_module)
+                .filter(|dependency| old_version.is_none() || dependency.locked_version == old_version);
+            match new {
+                // A local directory, which is built from whatever is there.
+                [dir] => {
+                    let dir = manifest_dir.join(unquote(dir));
+                    let go_mod_path = dir.join(MANIFEST_FILE_NAME);
+                    let replacement = module_path(&go_mod_path, &parse_directives(&read_to_string(&go_mod_path)?))?;
+                    let replacement_version = version_file(&dir)?;
+                    for dependency in replaced {
+                        dependency.name = replacement.clone();
+                        dependency.path = Some(dir.clone());
+                        if let Some(version) = &replacement_version {
+                            dependency.locked_version = Some(version.clone());
+                        }
+                    }
+                },
+                [module, version] => {
+                    for dependency in replaced {
+                        dependency.name = unquote(module);
+                        dependency.locked_version = Some(unquote(version));
+                    }
+                },
+                _ => ()
+            }
+        }
+        Ok(GoModule {
+            name,
+            version,
+            dependencies,
+        })
+    }
+}
+
+impl ManifestReader for GoModule {
+    fn build_system(&self) -> &'static str {
+        GO_BUILD_SYSTEM
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    fn dependencies(&self) -> &[Dependency] {
+        &self.dependencies
+    }
+
+    fn matching_versions(&self, _requirement: &str, _versions: &[String]) -> Option<Vec<String>> {
+        // Every requirement in a go.mod is the exact version that's built.
+        None
+    }
+}
+
+/// Splits a go.mod into its directives, expanding blocks such as `require ( ... )` into one
+/// directive per line.
+///
+/// https://go.dev/ref/mod#go-mod-file-grammar
+fn parse_directives(contents: &str) -> Vec<Directive<'_>> {
+    let mut directives = vec![];
+    let mut block: Option<&str> = None;
+    for line in contents.lines() {
+        let line = line.split("//").next().unwrap_or("").trim();
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        match (block, words.as_slice()) {
+            (_, []) => (),
+            (Some(_), [")"]) => block = None,
+            (Some(verb), _) => directives.push(Directive { verb, words }),
+            (None, [verb, "("]) => block = Some(verb),
+            (None, [_, ..]) => {
+                let verb = words.remove(0);
+                directives.push(Directive { verb, words });
+            },
+        }
+    }
+    directives
+}
+
+fn module_path(path: &Path, directives: &[Directive]) -> Result<String, Error> {
+    match directives.iter().find(|directive| directive.verb == "module").and_then(|directive| directive.words.first()) {
+        Some(module) => Ok(unquote(module)),
+        None => Err(Error::InvalidManifest {
+            path: path.to_path_buf(),
+            reason: String::from("No module directive present in go.mod"),
+        })
+    }
+}
+
+/// The version in the VERSION file in `dir`, if there is one.
+fn version_file(dir: &Path) -> Result<Option<String>, Error> {
+    let path = dir.join(VERSION_FILE_NAME);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let version = read_to_string(&path)?;
+    let version = version.trim();
+    if version.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(normalize_version(version)))
+}
+
+/// Go versions are git tags such as "v1.2.3", so "refs/tags/v1.2.3" and "1.2.3" become "v1.2.3".
+fn normalize_version(version: &str) -> String {
+    let version = normalize_git_ref(version.trim());
+    if version.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("v{}", version)
+    } else {
+        version
+    }
+}
+
+fn unquote(word: &str) -> String {
+    word.trim_matches(|c| c == '"' || c == '`').to_string()
+}
+
+fn read_to_string(path: &Path) -> Result<String, Error> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents),
+        Err(err) => Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_project;
+
+    const GO_MOD: &str = r#"
+module "example.com/app" // the app
+
+go 1.21
+
+require example.com/single v1.0.0
+require (
+    example.com/lib v1.2.3
+    example.com/forked v0.4.0 // indirect
+    example.com/local v0.0.0-00010101000000-000000000000
+)
+
+replace example.com/forked v0.4.0 => example.com/fork v0.4.1
+replace example.com/local => ./local
+"#;
+
+    fn dependencies(module: &GoModule) -> Vec<(&str, Option<&str>, bool)> {
+        module.dependencies().iter()
+            .map(|dependency| (dependency.name.as_str(), dependency.locked_version.as_deref(), dependency.path.is_some()))
+            .collect()
+    }
+
+    #[test]
+    fn splits_directives_and_blocks() {
+        let directives = parse_directives(GO_MOD);
+        let verbs: Vec<&str> = directives.iter().map(|directive| directive.verb).collect();
+        assert_eq!(verbs, ["module", "go", "require", "require", "require", "require", "replace", "replace"]);
+        assert_eq!(directives[4].words, ["example.com/forked", "v0.4.0"]);
+    }
+
+    #[test]
+    fn reads_requirements_and_replacements() {
+        let dir = temp_project("go", &[
+            ("go.mod", GO_MOD),
+            ("VERSION", "1.4.0\n"),
+            ("local/go.mod", "module example.com/local\n"),
+            ("local/VERSION", "v0.9.0"),
+        ]);
+        let module = GoModule::from_path(dir.join("go.mod"), None).unwrap();
+        assert_eq!((module.name(), module.version()), (String::from("example.com/app"), String::from("v1.4.0")));
+        assert_eq!(dependencies(&module), [
+            ("example.com/single", Some("v1.0.0"), false),
+            ("example.com/lib", Some("v1.2.3"), false),
+            ("example.com/fork", Some("v0.4.1"), false),
+            ("example.com/local", Some("v0.9.0"), true),
+        ]);
+    }
+
+    #[test]
+    fn the_given_version_wins_over_the_version_file() {
+        let dir = temp_project("go-version", &[("go.mod", "module example.com/m\n"), ("VERSION", "1.0.0")]);
+        let module = GoModule::from_path(dir.join("go.mod"), Some("refs/tags/v2.0.0")).unwrap();
+        assert_eq!(module.version(), "v2.0.0");
+    }
+
+    #[test]
+    fn a_version_is_required() {
+        let dir = temp_project("go-no-version", &[("go.mod", "module example.com/m\n")]);
+        assert!(matches!(GoModule::from_path(dir.join("go.mod"), None), Err(Error::InvalidManifest { .. })));
+    }
+}
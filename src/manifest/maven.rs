@@ -0,0 +1,483 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use roxmltree::{Document, Node};
+use crate::error::Error;
+use crate::manifest::{manifest_dir, parse_interval_ranges, Dependency, DependencyKind, ManifestReader};
+use crate::package_key::MAVEN_BUILD_SYSTEM;
+
+const MANIFEST_FILE_NAME: &str = "pom.xml";
+const DEFAULT_PARENT_PATH: &str = "../pom.xml";
+/// Parents deeper than this are assumed to be a cycle of relativePaths.
+const MAX_PARENT_DEPTH: usize = 16;
+/// Properties that refer to other properties are substituted at most this many times.
+const MAX_SUBSTITUTIONS: usize = 16;
+
+const SCOPE_TEST: &str = "test";
+const SCOPE_PROVIDED: &str = "provided";
+const SCOPE_IMPORT: &str = "import";
+
+/// The parts of a pom.xml that packages and their dependencies are read from, before inheritance
+/// and property substitution.
+struct Pom {
+    group_id: Option<String>,
+    artifact_id: Option<String>,
+    version: Option<String>,
+    parent: Option<Parent>,
+    properties: Vec<(String, String)>,
+    /// `<dependencyManagement>` versions, keyed by "groupId:artifactId".
+    managed_versions: Vec<(String, String)>,
+    dependencies: Vec<PomDependency>,
+}
+
+struct Parent {
+    group_id: String,
+    artifact_id: String,
+    version: String,
+    /// The parent's pom.xml, if it's expected to be in the same source tree.
+    path: Option<PathBuf>,
+}
+
+struct PomDependency {
+    group_id: String,
+    artifact_id: String,
+    version: Option<String>,
+    scope: Option<String>,
+}
+
+/// A Maven project's coordinates and dependencies, read from its pom.xml.
+pub struct MavenPackage {
+    name: String,
+    version: String,
+    dependencies: Vec<Dependency>,
+}
+
+impl MavenPackage {
+    /// Reads the pom.xml at `path`. The group and version may be inherited from its parent, and
+    /// properties are inherited from any parent pom.xml found at the parent's `relativePath`.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let pom = Pom::from_path(path)?;
+        let ancestors = pom.ancestors()?;
+
+        let parent = pom.parent.as_ref();
+        let group_id = pom.group_id.clone().or_else(|| parent.map(|parent| parent.group_id.clone()));
+        let version = pom.version.clone().or_else(|| parent.map(|parent| parent.version.clone()));
+        let (group_id, artifact_id, version) = match (group_id, pom.artifact_id.clone(), version) {
+            (Some(group_id), Some(artifact_id), Some(version)) => (group_id, artifact_id, version),
+            _ => return Err(Error::InvalidManifest {
+                path: path.to_path_buf(),
+                reason: String::from("No groupId, artifactId and version present in pom.xml or its parent"),
+            })
+        };
+
+        // https://maven.apache.org/guides/introduction/introduction-to-the-pom.html#project-interpolation-and-variables
+        // Built-in properties go in first so that a project's own <properties> take precedence.
+        let mut properties: HashMap<String, String> = HashMap::new();
+        for prefix in ["project.", "pom.", ""] {
+            properties.insert(format!("{}groupId", prefix), group_id.clone());
+            properties.insert(format!("{}artifactId", prefix), artifact_id.clone());
+            properties.insert(format!("{}version", prefix), version.clone());
+        }
+        if let Some(parent) = parent {
+            properties.insert(String::from("project.parent.groupId"), parent.group_id.clone());
+            properties.insert(String::from("project.parent.version"), parent.version.clone());
+        }
+        for inherited in ancestors.iter().rev().chain([&pom]) {
+            properties.extend(inherited.properties.iter().cloned());
+        }
+        let group_id = substitute(&group_id, &properties);
+        let version = substitute(&version, &properties);
+        // Keyed the same way as the dependencies looked up in it, e.g. a BOM entry for
+        // "${project.groupId}:core" matches a dependency on "com.example:core".
+        let managed_versions: HashMap<String, String> = ancestors.iter().rev().chain([&pom])
+            .flat_map(|inherited| inherited.managed_versions.iter())
+            .map(|(name, version)| (substitute(name, &properties), version.clone()))
+            .collect();
+
+        let mut dependencies = vec![];
+        // A change to the parent changes the build configuration the project inherits.
+        if let Some(parent) = parent {
+            dependencies.push(Dependency {
+                name: format!("{}:{}", substitute(&parent.group_id, &properties), parent.artifact_id),
+                version: Some(substitute(&parent.version, &properties)),
+                path: None,
+                locked_version: Some(substitute(&parent.version, &properties)),
+                git: None,
+                registry: None,
+                kind: DependencyKind::Normal,
+            });
+        }
+        for dep in &pom.dependencies {
+            let name = format!("{}:{}", substitute(&dep.group_id, &properties), substitute(&dep.artifact_id, &properties));
+            let version = dep.version.as_ref().or_else(|| managed_versions.get(&name))
+                .map(|version| substitute(version, &properties));
+            let kind = match dep.scope.as_ref().map(|scope| substitute(scope, &properties)).as_deref() {
+                Some(SCOPE_TEST) => DependencyKind::Dev,
+                Some(SCOPE_PROVIDED) => DependencyKind::Build,
+                Some(SCOPE_IMPORT) => continue,
+                _ => DependencyKind::Normal,
+            };
+            dependencies.push(Dependency {
+                name,
+                version,
+                path: None,
+                locked_version: None,
+                git: None,
+                registry: None,
+                kind,
+            });
+        }
+        Ok(MavenPackage {
+            name: format!("{}:{}", group_id, artifact_id),
+            version,
+            dependencies,
+        })
+    }
+}
+
+impl ManifestReader for MavenPackage {
+    fn build_system(&self) -> &'static str {
+        MAVEN_BUILD_SYSTEM
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    fn dependencies(&self) -> &[Dependency] {
+        &self.dependencies
+    }
+
+    fn matching_versions(&self, requirement: &str, versions: &[String]) -> Option<Vec<String>> {
+        // https://maven.apache.org/pom.html#dependency-version-requirement-specification
+        // Anything but a range, such as "1.0", is a soft requirement that Maven resolves to that
+        // version.
+        if !requirement.starts_with(['[', '(']) {
+            return None;
+        }
+        let ranges = parse_interval_ranges(requirement)?;
+        let mut matching_versions: Vec<String> = versions.iter()
+            .filter(|version| ranges.iter().any(|range| range.contains(version, compare_versions)))
+            .cloned()
+            .collect();
+        matching_versions.sort_by(|a, b| compare_versions(a, b));
+        Some(matching_versions)
+    }
+}
+
+impl Pom {
+    fn from_path(path: &Path) -> Result<Pom, Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => return Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+        };
+        let document = match Document::parse(&contents) {
+            Ok(document) => document,
+            Err(err) => return Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+        };
+        let project = document.root_element();
+
+        let parent = match child(project, "parent") {
+            Some(parent) => match (child_text(parent, "groupId"), child_text(parent, "artifactId"), child_text(parent, "version")) {
+                (Some(group_id), Some(artifact_id), Some(version)) => {
+                    // An empty <relativePath/> means the parent only comes from a repository.
+                    let relative_path = match child(parent, "relativePath") {
+                        Some(relative_path) => relative_path.text().map(|text| text.trim().to_string()).filter(|text| !text.is_empty()),
+                        None => Some(String::from(DEFAULT_PARENT_PATH)),
+                    };
+                    let path = relative_path.map(|relative_path| {
+                        let path = manifest_dir(path).join(relative_path);
+                        if path.is_dir() { path.join(MANIFEST_FILE_NAME) } else { path }
+                    });
+                    Some(Parent { group_id, artifact_id, version, path })
+                },
+                _ => return Err(Error::InvalidManifest {
+                    path: path.to_path_buf(),
+                    reason: String::from("<parent> must have a groupId, artifactId and version"),
+                })
+            },
+            None => None
+        };
+        let properties = match child(project, "properties") {
+            Some(properties) => properties.children()
+                .filter(|property| property.is_element())
+                .map(|property| (property.tag_name().name().to_string(), property.text().unwrap_or("").trim().to_string()))
+                .collect(),
+            None => vec![],
+        };
+        let managed_versions = child(project, "dependencyManagement")
+            .map(dependencies_of)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|dep| dep.version.map(|version| (format!("{}:{}", dep.group_id, dep.artifact_id), version)))
+            .collect();
+
+        Ok(Pom {
+            group_id: child_text(project, "groupId"),
+            artifact_id: child_text(project, "artifactId"),
+            version: child_text(project, "version"),
+            parent,
+            properties,
+            managed_versions,
+            dependencies: dependencies_of(project),
+        })
+    }
+
+    /// The chain of parent poms in the source tree, nearest first. It stops at the first parent
+    /// that isn't there or is a different project than the one declared, which Maven would fetch
+    /// from a repository instead.
+    fn ancestors(&self) -> Result<Vec<Pom>, Error> {
+        let mut ancestors: Vec<Pom> = vec![];
+        let mut parent = self.parent.as_ref();
+        while let Some(Parent { group_id, artifact_id, path: Some(path), .. }) = parent {
+            if ancestors.len() == MAX_PARENT_DEPTH || !path.is_file() {
+                break;
+            }
+            let pom = Pom::from_path(path)?;
+            let pom_group_id = pom.group_id.as_ref().or_else(|| pom.parent.as_ref().map(|parent| &parent.group_id));
+            if pom_group_id != Some(group_id) || pom.artifact_id.as_ref() != Some(artifact_id) {
+                break;
+            }
+            ancestors.push(pom);
+            parent = ancestors.last().and_then(|pom| pom.parent.as_ref());
+        }
+        Ok(ancestors)
+    }
+}
+
+/// The `<dependencies>` directly under `node`.
+fn dependencies_of(node: Node) -> Vec<PomDependency> {
+    let dependencies = match child(node, "dependencies") {
+        Some(dependencies) => dependencies,
+        None => return vec![]
+    };
+    dependencies.children()
+        .filter(|dependency| dependency.has_tag_name("dependency"))
+        .filter_map(|dependency| Some(PomDependency {
+            group_id: child_text(dependency, "groupId")?,
+            artifact_id: child_text(dependency, "artifactId")?,
+            version: child_text(dependency, "version"),
+            scope: child_text(dependency, "scope"),
+        }))
+        .collect()
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Replaces `${name}` with the property's value. Unknown properties are left as they are.
+fn substitute(value: &str, properties: &HashMap<String, String>) -> String {
+    let mut value = value.to_string();
+    // Everything before `from` is either plain text or an unknown property.
+    let mut from = 0;
+    let mut substitutions = 0;
+    while substitutions < MAX_SUBSTITUTIONS {
+        let (start, end) = match value[from..].find("${").map(|start| from + start).and_then(|start| value[start..].find('}').map(|end| (start, start + end))) {
+            Some(bounds) => bounds,
+            None => break
+        };
+        match properties.get(&value[start + 2..end]) {
+            Some(property) => {
+                value.replace_range(start..=end, property);
+                substitutions += 1;
+            },
+            None => from = end + 1
+        }
+    }
+    value
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum VersionToken {
+    Number(u64),
+    Qualifier(String),
+}
+
+/// Compares versions roughly the way Maven does: numbers numerically, and qualifiers ordered
+/// alpha < beta < milestone < rc < snapshot < release < sp, with unknown qualifiers after those.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a, b) = (tokenize(a), tokenize(b));
+    for index in 0..a.len().max(b.len()) {
+        let ordering = match (a.get(index), b.get(index)) {
+            (Some(a), Some(b)) => compare_tokens(a, b),
+            (Some(a), None) => compare_tokens(a, &padding(a)),
+            (None, Some(b)) => compare_tokens(&padding(b), b),
+            (None, None) => Ordering::Equal,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// What a missing token compares as: 0 against a number, a release against a qualifier.
+fn padding(token: &VersionToken) -> VersionToken {
+    match token {
+        VersionToken::Number(_) => VersionToken::Number(0),
+        VersionToken::Qualifier(_) => VersionToken::Qualifier(String::new()),
+    }
+}
+
+fn compare_tokens(a: &VersionToken, b: &VersionToken) -> Ordering {
+    match (a, b) {
+        (VersionToken::Number(a), VersionToken::Number(b)) => a.cmp(b),
+        (VersionToken::Number(_), VersionToken::Qualifier(_)) => Ordering::Greater,
+        (VersionToken::Qualifier(_), VersionToken::Number(_)) => Ordering::Less,
+        (VersionToken::Qualifier(a), VersionToken::Qualifier(b)) => qualifier_rank(a).cmp(&qualifier_rank(b)).then_with(|| a.cmp(b)),
+    }
+}
+
+fn qualifier_rank(qualifier: &str) -> u8 {
+    match qualifier {
+        "alpha" | "a" => 0,
+        "beta" | "b" => 1,
+        "milestone" | "m" => 2,
+        "rc" | "cr" => 3,
+        "snapshot" => 4,
+        "" | "ga" | "final" | "release" => 5,
+        "sp" => 6,
+        _ => 7,
+    }
+}
+
+/// Splits a version on '.' and '-', and wherever digits and letters meet ("1.0rc1" is 1, 0, rc, 1).
+fn tokenize(version: &str) -> Vec<VersionToken> {
+    let mut tokens = vec![];
+    for part in version.to_lowercase().split(['.', '-']) {
+        let mut current = String::new();
+        for c in part.chars() {
+            if !current.is_empty() && current.chars().all(|d| d.is_ascii_digit()) != c.is_ascii_digit() {
+                tokens.push(to_token(&current));
+                current.clear();
+            }
+            current.push(c);
+        }
+        if !current.is_empty() {
+            tokens.push(to_token(&current));
+        }
+    }
+    tokens
+}
+
+fn to_token(part: &str) -> VersionToken {
+    match part.parse() {
+        Ok(number) => VersionToken::Number(number),
+        Err(_) => VersionToken::Qualifier(part.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_project;
+
+    fn matching(requirement: &str, versions: &[&str]) -> Option<Vec<String>> {
+        let package = MavenPackage { name: String::from("com.example:consumer"), version: String::from("1.0"), dependencies: vec![] };
+        let versions: Vec<String> = versions.iter().map(|version| version.to_string()).collect();
+        package.matching_versions(requirement, &versions)
+    }
+
+    #[test]
+    fn versions_are_ordered_like_maven() {
+        let ordered = ["1.0-alpha1", "1.0-beta", "1.0-M1", "1.0-rc1", "1.0-SNAPSHOT", "1.0", "1.0-sp1", "1.0.1", "1.10"];
+        for pair in ordered.windows(2) {
+            assert_eq!(compare_versions(pair[0], pair[1]), Ordering::Less, "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(compare_versions("1.0", "1.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0rc1", "1.0-rc-1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn ranges_link_every_match() {
+        let versions = ["1.0", "1.5", "2.0-SNAPSHOT", "2.0", "3.1"];
+        assert_eq!(matching("[1.0,2.0)", &versions).unwrap(), ["1.0", "1.5", "2.0-SNAPSHOT"]);
+        assert_eq!(matching("[1.5]", &versions).unwrap(), ["1.5"]);
+        assert_eq!(matching("(,1.0],[3.0,)", &versions).unwrap(), ["1.0", "3.1"]);
+    }
+
+    #[test]
+    fn soft_requirements_are_exact() {
+        assert!(matching("1.5", &["1.5", "2.0"]).is_none());
+    }
+
+    #[test]
+    fn inherits_coordinates_properties_and_managed_versions() {
+        let dir = temp_project("maven", &[
+            ("pom.xml", r#"<project>
+                <groupId>com.example</groupId>
+                <artifactId>parent</artifactId>
+                <version>2.0.0</version>
+                <properties><junit.version>5.10.0</junit.version></properties>
+                <dependencyManagement><dependencies>
+                    <dependency><groupId>${project.groupId}</groupId><artifactId>core</artifactId><version>${project.version}</version></dependency>
+                </dependencies></dependencyManagement>
+            </project>"#),
+            ("app/pom.xml", r#"<project>
+                <parent><groupId>com.example</groupId><artifactId>parent</artifactId><version>2.0.0</version></parent>
+                <artifactId>app</artifactId>
+                <dependencies>
+                    <dependency><groupId>com.example</groupId><artifactId>core</artifactId></dependency>
+                    <dependency><groupId>org.junit</groupId><artifactId>junit</artifactId><version>${junit.version}</version><scope>test</scope></dependency>
+                    <dependency><groupId>com.example</groupId><artifactId>bom</artifactId><version>1.0</version><scope>import</scope></dependency>
+                </dependencies>
+            </project>"#),
+        ]);
+        let package = MavenPackage::from_path(dir.join("app/pom.xml")).unwrap();
+        assert_eq!((package.name(), package.version()), (String::from("com.example:app"), String::from("2.0.0")));
+        let dependencies: Vec<(&str, Option<&str>, DependencyKind)> = package.dependencies().iter()
+            .map(|dependency| (dependency.name.as_str(), dependency.version.as_deref(), dependency.kind))
+            .collect();
+        assert_eq!(dependencies, [
+            ("com.example:parent", Some("2.0.0"), DependencyKind::Normal),
+            ("com.example:core", Some("2.0.0"), DependencyKind::Normal),
+            ("org.junit:junit", Some("5.10.0"), DependencyKind::Dev),
+        ]);
+    }
+
+    #[test]
+    fn substitutes_past_unknown_properties() {
+        let properties = HashMap::from([
+            (String::from("known"), String::from("1.0")),
+            (String::from("nested"), String::from("${known}-${unknown}")),
+        ]);
+        assert_eq!(substitute("${unknown}-${known}", &properties), "${unknown}-1.0");
+        assert_eq!(substitute("${nested}.${known}", &properties), "1.0-${unknown}.1.0");
+        assert_eq!(substitute("${unclosed", &properties), "${unclosed");
+    }
+
+    #[test]
+    fn own_properties_win_over_built_in_ones() {
+        let dir = temp_project("maven-properties", &[
+            ("pom.xml", r#"<project>
+                <groupId>com.example</groupId>
+                <artifactId>app</artifactId>
+                <version>2.0.0</version>
+                <properties><version>1.4.0</version></properties>
+                <dependencies>
+                    <dependency><groupId>com.example</groupId><artifactId>core</artifactId><version>${version}</version></dependency>
+                    <dependency><groupId>com.example</groupId><artifactId>api</artifactId><version>${project.version}</version></dependency>
+                </dependencies>
+            </project>"#),
+        ]);
+        let package = MavenPackage::from_path(dir.join("pom.xml")).unwrap();
+        let dependencies: Vec<(&str, Option<&str>)> = package.dependencies().iter()
+            .map(|dependency| (dependency.name.as_str(), dependency.version.as_deref()))
+            .collect();
+        assert_eq!(dependencies, [("com.example:core", Some("1.4.0")), ("com.example:api", Some("2.0.0"))]);
+    }
+}
@@ -0,0 +1,448 @@
+mod dotnet;
+mod go;
+mod maven;
+mod npm;
+mod python;
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use semver::{Version, VersionReq};
+use crate::crate_helper::CrateHelper;
+use crate::error::Error;
+use crate::package_key::{BUILD_SYSTEM, GO_BUILD_SYSTEM, MAVEN_BUILD_SYSTEM, NPM_BUILD_SYSTEM, NUGET_BUILD_SYSTEM, PYPI_BUILD_SYSTEM};
+use crate::workspace::Workspace;
+
+pub use dotnet::NugetPackage;
+pub use go::GoModule;
+pub use maven::MavenPackage;
+pub use npm::NpmPackage;
+pub use python::PythonPackage;
+
+const KIND_NORMAL: &str = "normal";
+const KIND_DEV: &str = "dev";
+const KIND_BUILD: &str = "build";
+const KIND_TARGET: &str = "target";
+const KIND_PEER: &str = "peer";
+
+const ECOSYSTEM_CARGO: &str = "cargo";
+const ECOSYSTEM_NPM: &str = "npm";
+const ECOSYSTEM_MAVEN: &str = "maven";
+const ECOSYSTEM_PYTHON: &str = "python";
+const ECOSYSTEM_GO: &str = "go";
+const ECOSYSTEM_DOTNET: &str = "dotnet";
+
+const CARGO_MANIFEST_FILE_NAME: &str = "Cargo.toml";
+const NPM_MANIFEST_FILE_NAME: &str = "package.json";
+const MAVEN_MANIFEST_FILE_NAME: &str = "pom.xml";
+const PYTHON_MANIFEST_FILE_NAME: &str = "pyproject.toml";
+const PYTHON_REQUIREMENTS_FILE_NAME: &str = "requirements.txt";
+const GO_MANIFEST_FILE_NAME: &str = "go.mod";
+const DOTNET_PROJECT_EXTENSION: &str = "csproj";
+
+/// Which section of a manifest a dependency was declared in.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DependencyKind {
+    /// Needed to build and run the package, e.g. `[dependencies]`.
+    Normal,
+    /// Only needed for tests and local development, e.g. `[dev-dependencies]`.
+    Dev,
+    /// Only needed to build the package, e.g. `[build-dependencies]` or Maven's `provided` scope.
+    Build,
+    /// Cargo dependencies under `[target.'cfg(...)'.dependencies]`.
+    Target,
+    /// npm `peerDependencies`, which the consumer's own consumers provide.
+    Peer,
+}
+
+impl DependencyKind {
+    pub const ALL: [DependencyKind; 5] = [DependencyKind::Normal, DependencyKind::Dev, DependencyKind::Build, DependencyKind::Target, DependencyKind::Peer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DependencyKind::Normal => KIND_NORMAL,
+            DependencyKind::Dev => KIND_DEV,
+            DependencyKind::Build => KIND_BUILD,
+            DependencyKind::Target => KIND_TARGET,
+            DependencyKind::Peer => KIND_PEER,
+        }
+    }
+}
+
+impl fmt::Display for DependencyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DependencyKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            KIND_NORMAL => Ok(DependencyKind::Normal),
+            KIND_DEV => Ok(DependencyKind::Dev),
+            KIND_BUILD => Ok(DependencyKind::Build),
+            KIND_TARGET => Ok(DependencyKind::Target),
+            KIND_PEER => Ok(DependencyKind::Peer),
+            _ => Err(format!("Unknown dependency kind \"{}\". Expected one of: {}, {}, {}, {}, {}", kind, KIND_NORMAL, KIND_DEV, KIND_BUILD, KIND_TARGET, KIND_PEER)),
+        }
+    }
+}
+
+/// A git repository at a branch, tag or revision, or at its default branch if `reference` is
+/// `None`.
+#[derive(Clone, Debug)]
+pub struct GitSource {
+    pub url: String,
+    pub reference: Option<String>,
+}
+
+/// A dependency declared in a manifest. `version` is the requirement as written, if any.
+pub struct Dependency {
+    /// The package's own name, even if the manifest refers to it by another name.
+    pub name: String,
+    pub version: Option<String>,
+    /// The directory of a path dependency, relative to the current directory.
+    pub path: Option<PathBuf>,
+    /// The exact version the dependency resolves to, from a lock file or the manifest at its
+    /// path.
+    pub locked_version: Option<String>,
+    /// The repository of a git dependency.
+    pub git: Option<GitSource>,
+    /// The registry the dependency is fetched from. `None` for git and path dependencies, and for
+    /// build systems whose manifests don't say.
+    pub registry: Option<String>,
+    pub kind: DependencyKind,
+}
+
+/// A package read from the manifest of one build system.
+pub trait ManifestReader: Send + Sync {
+    /// The build system prefix of the package's keys, e.g. "rust" or "npm".
+    fn build_system(&self) -> &'static str;
+
+    fn name(&self) -> String;
+
+    fn version(&self) -> String;
+
+    fn dependencies(&self) -> &[Dependency];
+
+    /// The versions in `versions` that satisfy `requirement`, lowest first, using the build
+    /// system's rules. Returns `None` if `requirement` isn't a requirement the build system
+    /// understands, in which case it's treated as an exact version.
+    fn matching_versions(&self, requirement: &str, versions: &[String]) -> Option<Vec<String>>;
+}
+
+/// A build system whose manifests can be read.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Ecosystem {
+    /// Cargo.toml
+    Cargo,
+    /// package.json
+    Npm,
+    /// pom.xml
+    Maven,
+    /// pyproject.toml, or a requirements.txt beside one
+    Python,
+    /// go.mod
+    Go,
+    /// *.csproj
+    Dotnet,
+}
+
+impl Ecosystem {
+    pub const ALL: [Ecosystem; 6] = [Ecosystem::Cargo, Ecosystem::Npm, Ecosystem::Maven, Ecosystem::Python, Ecosystem::Go, Ecosystem::Dotnet];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Ecosystem::Cargo => ECOSYSTEM_CARGO,
+            Ecosystem::Npm => ECOSYSTEM_NPM,
+            Ecosystem::Maven => ECOSYSTEM_MAVEN,
+            Ecosystem::Python => ECOSYSTEM_PYTHON,
+            Ecosystem::Go => ECOSYSTEM_GO,
+            Ecosystem::Dotnet => ECOSYSTEM_DOTNET,
+        }
+    }
+
+    /// The build system prefix of the keys of this ecosystem's packages.
+    pub fn build_system(&self) -> &'static str {
+        match self {
+            Ecosystem::Cargo => BUILD_SYSTEM,
+            Ecosystem::Npm => NPM_BUILD_SYSTEM,
+            Ecosystem::Maven => MAVEN_BUILD_SYSTEM,
+            Ecosystem::Python => PYPI_BUILD_SYSTEM,
+            Ecosystem::Go => GO_BUILD_SYSTEM,
+            Ecosystem::Dotnet => NUGET_BUILD_SYSTEM,
+        }
+    }
+
+    /// The ecosystem of the manifest at `path`, from its file name (or a .csproj extension).
+    pub fn of(path: &Path) -> Result<Ecosystem, Error> {
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some(CARGO_MANIFEST_FILE_NAME) => Ok(Ecosystem::Cargo),
+            Some(NPM_MANIFEST_FILE_NAME) => Ok(Ecosystem::Npm),
+            Some(MAVEN_MANIFEST_FILE_NAME) => Ok(Ecosystem::Maven),
+            Some(PYTHON_MANIFEST_FILE_NAME | PYTHON_REQUIREMENTS_FILE_NAME) => Ok(Ecosystem::Python),
+            Some(GO_MANIFEST_FILE_NAME) => Ok(Ecosystem::Go),
+            _ if path.extension().is_some_and(|extension| extension == DOTNET_PROJECT_EXTENSION) => Ok(Ecosystem::Dotnet),
+            _ => Err(Error::InvalidManifest {
+                path: path.to_path_buf(),
+                reason: format!("Unrecognized manifest file name. Expected {}, {}, {}, {}, {}, {} or a .{} project",
+                    CARGO_MANIFEST_FILE_NAME, NPM_MANIFEST_FILE_NAME, MAVEN_MANIFEST_FILE_NAME, PYTHON_MANIFEST_FILE_NAME,
+                    PYTHON_REQUIREMENTS_FILE_NAME, GO_MANIFEST_FILE_NAME, DOTNET_PROJECT_EXTENSION),
+            })
+        }
+    }
+
+    /// This ecosystem's manifests directly in `dir`. Only .NET can have more than one. A Python
+    /// project's requirements.txt is only its manifest if there's no pyproject.toml.
+    pub fn manifests_in(&self, dir: &Path) -> Vec<PathBuf> {
+        let file_name = match self {
+            Ecosystem::Cargo => CARGO_MANIFEST_FILE_NAME,
+            Ecosystem::Npm => NPM_MANIFEST_FILE_NAME,
+            Ecosystem::Maven => MAVEN_MANIFEST_FILE_NAME,
+            Ecosystem::Python if dir.join(PYTHON_MANIFEST_FILE_NAME).is_file() => PYTHON_MANIFEST_FILE_NAME,
+            Ecosystem::Python => PYTHON_REQUIREMENTS_FILE_NAME,
+            Ecosystem::Go => GO_MANIFEST_FILE_NAME,
+            Ecosystem::Dotnet => {
+                let mut projects: Vec<PathBuf> = match fs::read_dir(dir) {
+                    Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                        .filter(|path| path.is_file() && matches!(Ecosystem::of(path), Ok(Ecosystem::Dotnet)))
+                        .collect(),
+                    Err(_) => vec![],
+                };
+                projects.sort();
+                return projects;
+            }
+        };
+        let path = dir.join(file_name);
+        if path.is_file() { vec![path] } else { vec![] }
+    }
+}
+
+impl fmt::Display for Ecosystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Ecosystem {
+    type Err = String;
+
+    fn from_str(ecosystem: &str) -> Result<Self, Self::Err> {
+        match Ecosystem::ALL.iter().find(|candidate| candidate.as_str() == ecosystem) {
+            Some(ecosystem) => Ok(*ecosystem),
+            None => {
+                let names: Vec<&str> = Ecosystem::ALL.iter().map(|ecosystem| ecosystem.as_str()).collect();
+                Err(format!("Unknown ecosystem \"{}\". Expected one of: {}", ecosystem, names.join(", ")))
+            }
+        }
+    }
+}
+
+/// Finds the manifests in `dir` for each of `ecosystems` (every ecosystem if it's empty), so that
+/// a repository with several build systems registers a package for each. Fails if there are none.
+pub fn detect_manifests(dir: &Path, ecosystems: &[Ecosystem]) -> Result<Vec<PathBuf>, Error> {
+    let ecosystems = if ecosystems.is_empty() { &Ecosystem::ALL[..] } else { ecosystems };
+    let manifests: Vec<PathBuf> = ecosystems.iter()
+        .flat_map(|ecosystem| ecosystem.manifests_in(dir))
+        .collect();
+    if manifests.is_empty() {
+        let names: Vec<&str> = ecosystems.iter().map(|ecosystem| ecosystem.as_str()).collect();
+        return Err(Error::InvalidManifest {
+            path: dir.to_path_buf(),
+            reason: format!("No manifest found for {}", names.join(", ")),
+        });
+    }
+    Ok(manifests)
+}
+
+/// Reads the single package described by the manifest at `path`, using the reader of
+/// [`Ecosystem::of`] the path. `version` is the package's version for manifests that don't have
+/// one, such as go.mod.
+pub fn read_package(path: impl AsRef<Path>, version: Option<&str>) -> Result<Box<dyn ManifestReader>, Error> {
+    let path = path.as_ref();
+    match Ecosystem::of(path)? {
+        Ecosystem::Cargo => Ok(Box::new(CrateHelper::from_path(path)?)),
+        Ecosystem::Npm => Ok(Box::new(NpmPackage::from_path(path)?)),
+        Ecosystem::Maven => Ok(Box::new(MavenPackage::from_path(path)?)),
+        Ecosystem::Python => Ok(Box::new(PythonPackage::from_path(path)?)),
+        Ecosystem::Go => Ok(Box::new(GoModule::from_path(path, version)?)),
+        Ecosystem::Dotnet => Ok(Box::new(NugetPackage::from_path(path)?)),
+    }
+}
+
+/// Reads every package described by the manifest at `path`: each member of a Cargo workspace, in
+/// dependency order, or otherwise the one package.
+pub fn read_packages(path: impl AsRef<Path>, version: Option<&str>) -> Result<Vec<Box<dyn ManifestReader>>, Error> {
+    let path = path.as_ref();
+    if Ecosystem::of(path)? != Ecosystem::Cargo {
+        return Ok(vec![read_package(path, version)?]);
+    }
+    match Workspace::from_path(path)? {
+        Some(workspace) => Ok(workspace.members.into_iter()
+            .map(|member| Box::new(member) as Box<dyn ManifestReader>)
+            .collect()),
+        None => Ok(vec![read_package(path, version)?]),
+    }
+}
+
+/// The versions in `versions` that satisfy any of `requirements`, lowest first. Versions that
+/// aren't semver are skipped.
+pub(crate) fn semver_matches(requirements: &[VersionReq], versions: &[String]) -> Vec<String> {
+    let mut matching_versions: Vec<(Version, String)> = versions.iter()
+        .filter_map(|version| Version::parse(version).ok().map(|parsed| (parsed, version.clone())))
+        .filter(|(parsed, _)| requirements.iter().any(|requirement| requirement.matches(parsed)))
+        .collect();
+    matching_versions.sort();
+    matching_versions.into_iter().map(|(_, version)| version).collect()
+}
+
+/// One interval of a version range in the bracket notation Maven and NuGet share, e.g.
+/// "[1.0,2.0)". A missing bound is unbounded.
+pub(crate) struct VersionRange {
+    /// The bound and whether it's inclusive.
+    pub(crate) lower: Option<(String, bool)>,
+    pub(crate) upper: Option<(String, bool)>,
+}
+
+impl VersionRange {
+    /// Whether `version` is in the interval, using the build system's version ordering.
+    pub(crate) fn contains(&self, version: &str, compare: fn(&str, &str) -> Ordering) -> bool {
+        let above_lower = match &self.lower {
+            Some((lower, inclusive)) => match compare(version, lower) {
+                Ordering::Greater => true,
+                Ordering::Equal => *inclusive,
+                Ordering::Less => false,
+            },
+            None => true,
+        };
+        let below_upper = match &self.upper {
+            Some((upper, inclusive)) => match compare(version, upper) {
+                Ordering::Less => true,
+                Ordering::Equal => *inclusive,
+                Ordering::Greater => false,
+            },
+            None => true,
+        };
+        above_lower && below_upper
+    }
+}
+
+/// Parses a range such as "[1.0]", "[1.2,2.0)" or "(,1.0],[1.2,)". Returns `None` if it's
+/// malformed.
+pub(crate) fn parse_interval_ranges(requirement: &str) -> Option<Vec<VersionRange>> {
+    let mut ranges = vec![];
+    let mut rest = requirement.trim();
+    while !rest.is_empty() {
+        let lower_inclusive = match rest.chars().next()? {
+            '[' => true,
+            '(' => false,
+            _ => return None,
+        };
+        let end = rest.find([']', ')'])?;
+        let upper_inclusive = rest[end..].starts_with(']');
+        let bound = |version: &str, inclusive: bool| {
+            let version = version.trim();
+            if version.is_empty() { None } else { Some((version.to_string(), inclusive)) }
+        };
+        let range = match rest[1..end].split_once(',') {
+            Some((lower, upper)) => VersionRange {
+                lower: bound(lower, lower_inclusive),
+                upper: bound(upper, upper_inclusive),
+            },
+            // "[1.0]" is exactly 1.0.
+            None => VersionRange {
+                lower: bound(&rest[1..end], true),
+                upper: bound(&rest[1..end], true),
+            },
+        };
+        ranges.push(range);
+        rest = rest[end + 1..].trim_start().trim_start_matches(',').trim_start();
+    }
+    Some(ranges)
+}
+
+/// The directory containing a manifest, which relative paths in the manifest are resolved against.
+pub(crate) fn manifest_dir(manifest_path: &Path) -> PathBuf {
+    match manifest_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_project;
+
+    fn contains(requirement: &str, version: &str) -> bool {
+        parse_interval_ranges(requirement).unwrap().iter().any(|range| range.contains(version, |a, b| {
+            Version::parse(a).unwrap().cmp(&Version::parse(b).unwrap())
+        }))
+    }
+
+    #[test]
+    fn interval_ranges_respect_inclusive_bounds() {
+        assert!(contains("[1.0.0,2.0.0)", "1.0.0"));
+        assert!(contains("[1.0.0,2.0.0)", "1.9.9"));
+        assert!(!contains("[1.0.0,2.0.0)", "2.0.0"));
+        assert!(!contains("(1.0.0,2.0.0]", "1.0.0"));
+        assert!(contains("(1.0.0,2.0.0]", "2.0.0"));
+    }
+
+    #[test]
+    fn interval_ranges_can_be_unbounded_exact_or_unions() {
+        assert!(contains("[1.5.0,)", "99.0.0"));
+        assert!(contains("(,1.0.0]", "0.1.0"));
+        assert!(contains("[1.2.0]", "1.2.0"));
+        assert!(!contains("[1.2.0]", "1.2.1"));
+        assert!(contains("(,1.0.0],[1.2.0,)", "1.3.0"));
+        assert!(!contains("(,1.0.0],[1.2.0,)", "1.1.0"));
+    }
+
+    #[test]
+    fn malformed_interval_ranges_are_rejected() {
+        assert!(parse_interval_ranges("1.0").is_none());
+        assert!(parse_interval_ranges("[1.0").is_none());
+    }
+
+    #[test]
+    fn ecosystem_comes_from_the_file_name() {
+        assert_eq!(Ecosystem::of(Path::new("a/Cargo.toml")).unwrap(), Ecosystem::Cargo);
+        assert_eq!(Ecosystem::of(Path::new("package.json")).unwrap(), Ecosystem::Npm);
+        assert_eq!(Ecosystem::of(Path::new("pom.xml")).unwrap(), Ecosystem::Maven);
+        assert_eq!(Ecosystem::of(Path::new("pyproject.toml")).unwrap(), Ecosystem::Python);
+        assert_eq!(Ecosystem::of(Path::new("requirements.txt")).unwrap(), Ecosystem::Python);
+        assert_eq!(Ecosystem::of(Path::new("go.mod")).unwrap(), Ecosystem::Go);
+        assert_eq!(Ecosystem::of(Path::new("src/Acme.Widgets.csproj")).unwrap(), Ecosystem::Dotnet);
+        match Ecosystem::of(Path::new("build.gradle")) {
+            Err(Error::InvalidManifest { path, .. }) => assert_eq!(path, Path::new("build.gradle")),
+            _ => panic!("Expected build.gradle to be rejected"),
+        }
+    }
+
+    #[test]
+    fn detects_a_manifest_per_ecosystem() {
+        let dir = temp_project("detect", &[
+            ("package.json", "{}"),
+            ("go.mod", "module example.com/m\n"),
+            ("B.csproj", "<Project/>"),
+            ("A.csproj", "<Project/>"),
+        ]);
+        let manifests = detect_manifests(&dir, &[]).unwrap();
+        assert_eq!(manifests, [dir.join("package.json"), dir.join("go.mod"), dir.join("A.csproj"), dir.join("B.csproj")]);
+        assert_eq!(detect_manifests(&dir, &[Ecosystem::Go]).unwrap(), [dir.join("go.mod")]);
+        assert!(detect_manifests(&dir, &[Ecosystem::Cargo]).is_err());
+    }
+
+    #[test]
+    fn detects_python_projects_by_their_requirements_without_a_pyproject() {
+        let dir = temp_project("detect-python", &[("requirements.txt", "requests\n")]);
+        assert_eq!(detect_manifests(&dir, &[]).unwrap(), [dir.join("requirements.txt")]);
+        std::fs::write(dir.join("pyproject.toml"), "").unwrap();
+        assert_eq!(detect_manifests(&dir, &[]).unwrap(), [dir.join("pyproject.toml")]);
+    }
+}
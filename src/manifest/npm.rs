@@ -0,0 +1,363 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use semver::VersionReq;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use crate::error::Error;
+use crate::manifest::{manifest_dir, semver_matches, Dependency, DependencyKind, GitSource, ManifestReader};
+use crate::package_key::NPM_BUILD_SYSTEM;
+
+const MANIFEST_FILE_NAME: &str = "package.json";
+const LOCK_FILE_NAME: &str = "package-lock.json";
+
+/// The name npm gives its public registry, for dependencies that are fetched from a registry.
+pub const NPM_REGISTRY: &str = "npmjs";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageJson {
+    name: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    peer_dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct LockFile {
+    /// Lock file versions 2 and 3, keyed by install path (e.g. "node_modules/left-pad").
+    #[serde(default)]
+    packages: HashMap<String, LockedPackage>,
+    /// Lock file version 1, keyed by name.
+    #[serde(default)]
+    dependencies: HashMap<String, LockedPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    version: Option<String>,
+}
+
+/// A package's name, version and dependencies, read from its package.json.
+pub struct NpmPackage {
+    name: String,
+    version: String,
+    dependencies: Vec<Dependency>,
+}
+
+impl NpmPackage {
+    /// Reads the package.json at `path`, which must have a name and version.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let package_json: PackageJson = read_json(path)?;
+        let (name, version) = name_and_version(path, &package_json)?;
+        let manifest_dir = manifest_dir(path);
+        let package_lock = PackageLock::find(&manifest_dir)?;
+
+        let mut dependencies = vec![];
+        let sections = [
+            (&package_json.dependencies, DependencyKind::Normal),
+            (&package_json.optional_dependencies, DependencyKind::Normal),
+            (&package_json.dev_dependencies, DependencyKind::Dev),
+            (&package_json.peer_dependencies, DependencyKind::Peer),
+        ];
+        for (section, kind) in sections {
+            for (key, spec) in section {
+                match parse_spec(&manifest_dir, key, spec)? {
+                    Some(mut dependency) => {
+                        if dependency.locked_version.is_none() && dependency.git.is_none() {
+                            dependency.locked_version = package_lock.as_ref()
+                                .and_then(|package_lock| package_lock.locked_version(&manifest_dir, key));
+                        }
+                        dependency.kind = kind;
+                        dependencies.push(dependency);
+                    },
+                    None => log::info!("{} is fetched from \"{}\", which isn't tracked. Skipping...", key, spec)
+                }
+            }
+        }
+        Ok(NpmPackage {
+            name,
+            version,
+            dependencies,
+        })
+    }
+}
+
+impl ManifestReader for NpmPackage {
+    fn build_system(&self) -> &'static str {
+        NPM_BUILD_SYSTEM
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    fn dependencies(&self) -> &[Dependency] {
+        &self.dependencies
+    }
+
+    fn matching_versions(&self, requirement: &str, versions: &[String]) -> Option<Vec<String>> {
+        // https://docs.npmjs.com/cli/v8/using-npm/semver#ranges
+        let mut requirements = vec![];
+        for range in requirement.split("||") {
+            requirements.push(VersionReq::parse(&to_semver_requirement(range)?).ok()?);
+        }
+        Some(semver_matches(&requirements, versions))
+    }
+}
+
+/// The package-lock.json that applies to a package.
+struct PackageLock {
+    dir: PathBuf,
+    lock_file: LockFile,
+}
+
+impl PackageLock {
+    /// Reads the package-lock.json in `manifest_dir` or, for workspace packages, the nearest one
+    /// above it. Returns `None` if there isn't one.
+    fn find(manifest_dir: &Path) -> Result<Option<PackageLock>, Error> {
+        let manifest_dir = fs::canonicalize(manifest_dir).unwrap_or_else(|_| manifest_dir.to_path_buf());
+        match manifest_dir.ancestors().find(|dir| dir.join(LOCK_FILE_NAME).is_file()) {
+            Some(dir) => Ok(Some(PackageLock {
+                dir: dir.to_path_buf(),
+                lock_file: read_json(&dir.join(LOCK_FILE_NAME))?,
+            })),
+            None => Ok(None)
+        }
+    }
+
+    /// The exact version that the dependency installed as `key` by the package in `manifest_dir`
+    /// was locked to.
+    fn locked_version(&self, manifest_dir: &Path, key: &str) -> Option<String> {
+        let manifest_dir = fs::canonicalize(manifest_dir).unwrap_or_else(|_| manifest_dir.to_path_buf());
+        // Workspace packages get their own node_modules only for dependencies that conflict with
+        // the root's, so look there first.
+        let mut install_paths = vec![];
+        if let Ok(package_dir) = manifest_dir.strip_prefix(&self.dir) {
+            if !package_dir.as_os_str().is_empty() {
+                install_paths.push(format!("{}/node_modules/{}", package_dir.to_string_lossy(), key));
+            }
+        }
+        install_paths.push(format!("node_modules/{}", key));
+        let locked = install_paths.iter()
+            .find_map(|install_path| self.lock_file.packages.get(install_path))
+            .or_else(|| self.lock_file.dependencies.get(key))?;
+        // Aliased dependencies are locked as "npm:real-name@version" in version 1 lock files.
+        locked.version.as_ref().map(|version| match version.strip_prefix("npm:") {
+            Some(alias) => alias.rsplit_once('@').map_or(alias, |(_, version)| version).to_string(),
+            None => version.clone(),
+        })
+    }
+}
+
+/// Turns a dependency's key and spec into a [`Dependency`] of kind normal. Returns `None` for
+/// specs that can't be tracked, such as tarball URLs.
+///
+/// https://docs.npmjs.com/cli/v8/configuring-npm/package-json#dependencies
+fn parse_spec(manifest_dir: &Path, key: &str, spec: &str) -> Result<Option<Dependency>, Error> {
+    let mut dependency = Dependency {
+        name: key.to_string(),
+        version: None,
+        path: None,
+        locked_version: None,
+        git: None,
+        registry: None,
+        kind: DependencyKind::Normal,
+    };
+    // Workspace protocol used by pnpm and yarn, e.g. "workspace:^1.0.0".
+    let spec = spec.trim().trim_start_matches("workspace:");
+
+    if let Some(path) = spec.strip_prefix("file:").or_else(|| spec.strip_prefix("link:")) {
+        let path = manifest_dir.join(path);
+        let package_json_path = path.join(MANIFEST_FILE_NAME);
+        let (name, version) = name_and_version(&package_json_path, &read_json(&package_json_path)?)?;
+        dependency.name = name;
+        dependency.locked_version = Some(version);
+        dependency.path = Some(path);
+        return Ok(Some(dependency));
+    }
+    if let Some(git) = git_source(spec) {
+        dependency.git = Some(git);
+        return Ok(Some(dependency));
+    }
+    if spec.starts_with("http://") || spec.starts_with("https://") {
+        return Ok(None);
+    }
+    // Aliases, e.g. "npm:left-pad@^1.3.0" or "npm:@scope/name@1.0.0". The leading '@' of a scope
+    // isn't a version separator.
+    let range = match spec.strip_prefix("npm:") {
+        Some(alias) => match alias.get(1..).and_then(|rest| rest.rfind('@')) {
+            Some(index) => {
+                dependency.name = alias[..index + 1].to_string();
+                &alias[index + 2..]
+            },
+            None => {
+                dependency.name = alias.to_string();
+                ""
+            }
+        },
+        None => spec,
+    };
+    dependency.version = Some(range.to_string());
+    dependency.registry = Some(String::from(NPM_REGISTRY));
+    Ok(Some(dependency))
+}
+
+/// The repository of a git spec such as "git+https://host/repo.git#v1", "github:org/repo" or the
+/// "org/repo" shorthand for GitHub.
+fn git_source(spec: &str) -> Option<GitSource> {
+    let (url, reference) = match spec.split_once('#') {
+        Some((url, reference)) => (url, Some(reference.to_string())),
+        None => (spec, None),
+    };
+    let url = if url.starts_with("git+") || url.starts_with("git://") {
+        url.to_string()
+    } else if let Some(path) = url.strip_prefix("github:") {
+        format!("https://github.com/{}", path)
+    } else if let Some(path) = url.strip_prefix("gitlab:") {
+        format!("https://gitlab.com/{}", path)
+    } else if let Some(path) = url.strip_prefix("bitbucket:") {
+        format!("https://bitbucket.org/{}", path)
+    } else if url.contains('/') && !url.contains(':') && !url.starts_with('@') && !url.starts_with('.') {
+        format!("https://github.com/{}", url)
+    } else {
+        return None;
+    };
+    Some(GitSource { url, reference })
+}
+
+/// Rewrites one npm range (no "||") as a requirement the semver crate understands. Returns `None`
+/// for dist-tags such as "latest", which aren't ranges.
+fn to_semver_requirement(range: &str) -> Option<String> {
+    let range = range.trim();
+    if range.is_empty() || range == "*" || range == "x" || range == "X" {
+        return Some(String::from("*"));
+    }
+    // Hyphen ranges: "1.2.3 - 2.3.4" is inclusive at both ends.
+    if let Some((low, high)) = range.split_once(" - ") {
+        return Some(format!(">={}, <={}", low.trim().trim_start_matches('v'), high.trim().trim_start_matches('v')));
+    }
+    // Comparators are separated by spaces, but an operator may also be separated from its version.
+    let mut comparators: Vec<String> = vec![];
+    let mut operator = String::new();
+    for token in range.split_whitespace() {
+        if token.chars().all(|c| "<>=~^".contains(c)) {
+            operator.push_str(token);
+            continue;
+        }
+        let token = format!("{}{}", operator, token);
+        operator.clear();
+        let version_start = token.find(|c: char| !"<>=~^".contains(c)).unwrap_or(token.len());
+        let (op, version) = token.split_at(version_start);
+        let version = version.trim_start_matches('v');
+        if !version.starts_with(|c: char| c.is_ascii_digit() || c == 'x' || c == 'X' || c == '*') {
+            return None;
+        }
+        // A bare version is exact in npm but a caret requirement in Cargo.
+        let wildcard = version.contains(['x', 'X', '*']);
+        let op = if op.is_empty() && !wildcard { "=" } else { op };
+        comparators.push(format!("{}{}", op, version));
+    }
+    Some(comparators.join(", "))
+}
+
+fn name_and_version(path: &Path, package_json: &PackageJson) -> Result<(String, String), Error> {
+    match (&package_json.name, &package_json.version) {
+        (Some(name), Some(version)) => Ok((name.clone(), version.clone())),
+        _ => Err(Error::InvalidManifest {
+            path: path.to_path_buf(),
+            reason: String::from("No name and version present in package.json"),
+        })
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => return Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+    };
+    match serde_json::from_str(&contents) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_project;
+
+    fn matching(requirement: &str, versions: &[&str]) -> Option<Vec<String>> {
+        let package = NpmPackage { name: String::from("consumer"), version: String::from("1.0.0"), dependencies: vec![] };
+        let versions: Vec<String> = versions.iter().map(|version| version.to_string()).collect();
+        package.matching_versions(requirement, &versions)
+    }
+
+    #[test]
+    fn translates_npm_ranges() {
+        assert_eq!(to_semver_requirement("1.2.3").unwrap(), "=1.2.3");
+        assert_eq!(to_semver_requirement("v1.2.3").unwrap(), "=1.2.3");
+        assert_eq!(to_semver_requirement("^1.2.3").unwrap(), "^1.2.3");
+        assert_eq!(to_semver_requirement(">= 1.2.0 < 2").unwrap(), ">=1.2.0, <2");
+        assert_eq!(to_semver_requirement("1.2.3 - 2.0.0").unwrap(), ">=1.2.3, <=2.0.0");
+        assert_eq!(to_semver_requirement("1.x").unwrap(), "1.x");
+        assert_eq!(to_semver_requirement("").unwrap(), "*");
+        assert!(to_semver_requirement("latest").is_none());
+    }
+
+    #[test]
+    fn matches_ranges_and_alternatives() {
+        let versions = ["1.2.3", "1.4.0", "2.0.0", "3.1.0"];
+        assert_eq!(matching("1.2.3", &versions).unwrap(), ["1.2.3"]);
+        assert_eq!(matching("~1.2.0", &versions).unwrap(), ["1.2.3"]);
+        assert_eq!(matching("^1.2.0 || >=3", &versions).unwrap(), ["1.2.3", "1.4.0", "3.1.0"]);
+        assert_eq!(matching("1.3.0 - 2.0.0", &versions).unwrap(), ["1.4.0", "2.0.0"]);
+        assert_eq!(matching("*", &versions).unwrap(), versions);
+        assert!(matching("next", &versions).is_none());
+    }
+
+    #[test]
+    fn parses_aliases_git_and_urls() {
+        let dir = Path::new(".");
+        let alias = parse_spec(dir, "pad", "npm:@scope/left-pad@^1.3.0").unwrap().unwrap();
+        assert_eq!((alias.name.as_str(), alias.version.as_deref()), ("@scope/left-pad", Some("^1.3.0")));
+        let git = parse_spec(dir, "lib", "github:org/lib#v2").unwrap().unwrap().git.unwrap();
+        assert_eq!((git.url.as_str(), git.reference.as_deref()), ("https://github.com/org/lib", Some("v2")));
+        assert!(parse_spec(dir, "tarball", "https://host/lib.tgz").unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_sections_paths_and_locked_versions() {
+        let dir = temp_project("npm", &[
+            ("app/package.json", r#"{
+                "name": "app", "version": "1.0.0",
+                "dependencies": { "left-pad": "^1.3.0", "shared": "file:../shared" },
+                "devDependencies": { "jest": "^29.0.0" },
+                "peerDependencies": { "react": ">=17" }
+            }"#),
+            ("app/package-lock.json", r#"{ "packages": { "node_modules/left-pad": { "version": "1.3.0" } } }"#),
+            ("shared/package.json", r#"{ "name": "@acme/shared", "version": "0.2.0" }"#),
+        ]);
+        let package = NpmPackage::from_path(dir.join("app/package.json")).unwrap();
+        let dependencies: Vec<(&str, Option<&str>, DependencyKind)> = package.dependencies().iter()
+            .map(|dependency| (dependency.name.as_str(), dependency.locked_version.as_deref(), dependency.kind))
+            .collect();
+        assert_eq!(dependencies, [
+            ("left-pad", Some("1.3.0"), DependencyKind::Normal),
+            ("@acme/shared", Some("0.2.0"), DependencyKind::Normal),
+            ("jest", None, DependencyKind::Dev),
+            ("react", None, DependencyKind::Peer),
+        ]);
+    }
+}
@@ -0,0 +1,611 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use once_cell::sync::OnceCell;
+use regex::Regex;
+use serde::Deserialize;
+use crate::error::Error;
+use crate::manifest::{manifest_dir, Dependency, DependencyKind, GitSource, ManifestReader};
+use crate::package_key::PYPI_BUILD_SYSTEM;
+
+const MANIFEST_FILE_NAME: &str = "pyproject.toml";
+const REQUIREMENTS_FILE_NAME: &str = "requirements.txt";
+/// Poetry lists the interpreter as a dependency.
+const POETRY_PYTHON: &str = "python";
+
+/// The name of the Python Package Index, for dependencies that are fetched from an index.
+pub const PYPI_REGISTRY: &str = "pypi";
+
+// https://peps.python.org/pep-0440/#appendix-b-parsing-version-strings-with-regular-expressions
+const VERSION_REGEX: &str = r"(?i)^\s*v?(?:(\d+)!)?(\d+(?:\.\d+)*)(?:[-_.]?(a|b|c|rc|alpha|beta|pre|preview)[-_.]?(\d+)?)?(?:-(\d+)|[-_.]?(post|rev|r)[-_.]?(\d+)?)?(?:[-_.]?(dev)[-_.]?(\d+)?)?(?:\+[a-z0-9]+(?:[-_.][a-z0-9]+)*)?\s*$";
+
+fn version_pattern() -> &'static Regex {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    INSTANCE.get_or_init(|| Regex::new(VERSION_REGEX).unwrap())
+}
+
+#[derive(Deserialize)]
+struct PyProject {
+    project: Option<Project>,
+    tool: Option<Tool>,
+}
+
+/// https://packaging.python.org/en/latest/specifications/declaring-project-metadata/
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Project {
+    name: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct Tool {
+    poetry: Option<Poetry>,
+}
+
+/// https://python-poetry.org/docs/pyproject/
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Poetry {
+    name: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, toml::Value>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<String, toml::Value>,
+    #[serde(default)]
+    group: BTreeMap<String, PoetryGroup>,
+}
+
+#[derive(Deserialize)]
+struct PoetryGroup {
+    #[serde(default)]
+    dependencies: BTreeMap<String, toml::Value>,
+}
+
+/// A Python project's name, version and dependencies, read from its pyproject.toml and any
+/// requirements.txt beside it.
+pub struct PythonPackage {
+    name: String,
+    version: String,
+    dependencies: Vec<Dependency>,
+}
+
+impl PythonPackage {
+    /// Reads the pyproject.toml at `path`, which must have a name and version in either its
+    /// `[project]` or `[tool.poetry]` section.
+    ///
+    /// Requirements in a requirements.txt in the same directory are dependencies too, and the
+    /// versions it pins with `==` are the versions dependencies are linked to. `path` can be that
+    /// requirements.txt, but the pyproject.toml is still required for the project's name and
+    /// version.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if path.file_name().is_some_and(|file_name| file_name == REQUIREMENTS_FILE_NAME) {
+            let pyproject_path = manifest_dir(path).join(MANIFEST_FILE_NAME);
+            if !pyproject_path.is_file() {
+                return Err(Error::InvalidManifest {
+                    path: path.to_path_buf(),
+                    reason: format!("{} doesn't name the project. Add a {} with its name and version beside it, or pass --ecosystem to leave Python out",
+                                    REQUIREMENTS_FILE_NAME, MANIFEST_FILE_NAME),
+                });
+            }
+            return PythonPackage::from_path(pyproject_path);
+        }
+        let pyproject = read_pyproject(path)?;
+        let (name, version) = name_and_version(path, &pyproject)?;
+        let manifest_dir = manifest_dir(path);
+
+        let mut dependencies = vec![];
+        if let Some(project) = &pyproject.project {
+            let requirements = project.dependencies.iter().map(|requirement| (requirement, DependencyKind::Normal));
+            let optional = project.optional_dependencies.values().flatten().map(|requirement| (requirement, DependencyKind::Normal));
+            for (requirement, kind) in requirements.chain(optional) {
+                if let Some(dependency) = parse_requirement(&manifest_dir, requirement, kind)? {
+                    dependencies.push(dependency);
+                }
+            }
+        }
+        if let Some(poetry) = pyproject.tool.as_ref().and_then(|tool| tool.poetry.as_ref()) {
+            let main = poetry.dependencies.iter().map(|dep| (dep, DependencyKind::Normal));
+            let dev = poetry.dev_dependencies.iter()
+                .chain(poetry.group.values().flat_map(|group| group.dependencies.iter()))
+                .map(|dep| (dep, DependencyKind::Dev));
+            for ((key, spec), kind) in main.chain(dev) {
+                if key.eq_ignore_ascii_case(POETRY_PYTHON) {
+                    continue;
+                }
+                if let Some(dependency) = parse_poetry_dependency(&manifest_dir, key, spec, kind)? {
+                    dependencies.push(dependency);
+                }
+            }
+        }
+
+        let requirements_path = manifest_dir.join(REQUIREMENTS_FILE_NAME);
+        if requirements_path.is_file() {
+            let contents = match fs::read_to_string(&requirements_path) {
+                Ok(contents) => contents,
+                Err(err) => return Err(Error::Manifest { path: requirements_path, source: Box::new(err) })
+            };
+            // https://pip.pypa.io/en/stable/reference/requirements-file-format/
+            let lines = contents.lines()
+                .map(|line| line.split(" #").next().unwrap_or("").trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('-'));
+            for line in lines {
+                let pinned = match parse_requirement(&manifest_dir, line, DependencyKind::Normal)? {
+                    Some(pinned) => pinned,
+                    None => continue
+                };
+                let pinned_version = pinned.version.as_deref()
+                    .and_then(|version| version.strip_prefix("=="))
+                    .filter(|version| !version.contains(['*', ',']))
+                    .map(|version| version.trim().to_string());
+                let mut declared = false;
+                for dependency in dependencies.iter_mut().filter(|dependency| dependency.name == pinned.name) {
+                    declared = true;
+                    if dependency.locked_version.is_none() && dependency.git.is_none() {
+                        dependency.locked_version = pinned_version.clone();
+                    }
+                }
+                if !declared {
+                    dependencies.push(Dependency { locked_version: pinned_version, ..pinned });
+                }
+            }
+        }
+        Ok(PythonPackage {
+            name,
+            version,
+            dependencies,
+        })
+    }
+}
+
+impl ManifestReader for PythonPackage {
+    fn build_system(&self) -> &'static str {
+        PYPI_BUILD_SYSTEM
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    fn dependencies(&self) -> &[Dependency] {
+        &self.dependencies
+    }
+
+    fn matching_versions(&self, requirement: &str, versions: &[String]) -> Option<Vec<String>> {
+        // https://peps.python.org/pep-0440/#version-specifiers, plus Poetry's "^", "~" and "||".
+        let mut alternatives = vec![];
+        for alternative in requirement.split("||") {
+            let mut specifiers = vec![];
+            for clause in alternative.split(',').map(str::trim).filter(|clause| !clause.is_empty() && *clause != "*") {
+                specifiers.push(Specifier::parse(clause)?);
+            }
+            alternatives.push(specifiers);
+        }
+        // Pre-releases are only candidates if the requirement names one.
+        let allow_pre_releases = alternatives.iter().flatten()
+            .any(|specifier| specifier.version.as_ref().is_some_and(PythonVersion::is_pre_release));
+
+        let mut matching_versions: Vec<(PythonVersion, String)> = versions.iter()
+            .filter_map(|version| PythonVersion::parse(version).map(|parsed| (parsed, version.clone())))
+            .filter(|(parsed, _)| allow_pre_releases || !parsed.is_pre_release())
+            .filter(|(parsed, version)| alternatives.iter()
+                .any(|specifiers| specifiers.iter().all(|specifier| specifier.matches(parsed, version))))
+            .collect();
+        matching_versions.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Some(matching_versions.into_iter().map(|(_, version)| version).collect())
+    }
+}
+
+fn read_pyproject(path: &Path) -> Result<PyProject, Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => return Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+    };
+    match toml::from_str(&contents) {
+        Ok(pyproject) => Ok(pyproject),
+        Err(err) => Err(Error::Manifest { path: path.to_path_buf(), source: Box::new(err) })
+    }
+}
+
+fn name_and_version(path: &Path, pyproject: &PyProject) -> Result<(String, String), Error> {
+    let project = pyproject.project.as_ref();
+    let poetry = pyproject.tool.as_ref().and_then(|tool| tool.poetry.as_ref());
+    let name = project.and_then(|project| project.name.clone()).or_else(|| poetry.and_then(|poetry| poetry.name.clone()));
+    let version = project.and_then(|project| project.version.clone()).or_else(|| poetry.and_then(|poetry| poetry.version.clone()));
+    match (name, version) {
+        (Some(name), Some(version)) => Ok((normalize_name(&name), version)),
+        _ => Err(Error::InvalidManifest {
+            path: path.to_path_buf(),
+            reason: String::from("No name and version present in [project] or [tool.poetry]. Dynamic versions aren't supported"),
+        })
+    }
+}
+
+/// Turns a PEP 508 requirement such as "requests[socks]>=2.8; python_version >= '3.8'" or
+/// "lib @ git+https://host/repo.git@v1" into a [`Dependency`]. Returns `None` for direct
+/// references to archives, which can't be tracked.
+///
+/// https://peps.python.org/pep-0508/
+fn parse_requirement(manifest_dir: &Path, requirement: &str, kind: DependencyKind) -> Result<Option<Dependency>, Error> {
+    let requirement = requirement.split(';').next().unwrap_or("").trim();
+    let name_end = requirement.find(|c: char| !(c.is_ascii_alphanumeric() || "-_.".contains(c))).unwrap_or(requirement.len());
+    let mut dependency = Dependency {
+        name: normalize_name(&requirement[..name_end]),
+        version: None,
+        path: None,
+        locked_version: None,
+        git: None,
+        registry: None,
+        kind,
+    };
+    let mut rest = requirement[name_end..].trim_start();
+    // Extras don't change which package is depended on.
+    if rest.starts_with('[') {
+        rest = rest.find(']').map_or("", |end| rest[end + 1..].trim_start());
+    }
+    if let Some(url) = rest.strip_prefix('@') {
+        let url = url.trim().split('#').next().unwrap_or("");
+        if let Some(url) = url.strip_prefix("git+") {
+            // The ref follows the last '@' after the host, e.g. "https://host/repo.git@v1".
+            let (url, reference) = match url.rfind('@') {
+                Some(at) if at > url.rfind('/').unwrap_or(0) => (&url[..at], Some(url[at + 1..].to_string())),
+                _ => (url, None),
+            };
+            dependency.git = Some(GitSource { url: url.to_string(), reference });
+            return Ok(Some(dependency));
+        }
+        if let Some(path) = url.strip_prefix("file://").or_else(|| url.strip_prefix("file:")) {
+            return path_dependency(manifest_dir, dependency, path).map(Some);
+        }
+        return Ok(None);
+    }
+    let specifiers = rest.trim_start_matches('(').trim_end_matches(')').trim();
+    dependency.version = Some(if specifiers.is_empty() { String::from("*") } else { specifiers.to_string() });
+    dependency.registry = Some(String::from(PYPI_REGISTRY));
+    Ok(Some(dependency))
+}
+
+/// Turns a Poetry dependency, which is either a constraint or a table such as
+/// `{ version = "^1.2", extras = [...] }`, `{ git = "...", tag = "v1" }` or `{ path = "../lib" }`,
+/// into a [`Dependency`].
+///
+/// https://python-poetry.org/docs/dependency-specification/
+fn parse_poetry_dependency(manifest_dir: &Path, key: &str, spec: &toml::Value, kind: DependencyKind) -> Result<Option<Dependency>, Error> {
+    let mut dependency = Dependency {
+        name: normalize_name(key),
+        version: None,
+        path: None,
+        locked_version: None,
+        git: None,
+        registry: None,
+        kind,
+    };
+    // Multiple constraints are a list of tables that differ by marker; track the first.
+    let spec = match spec {
+        toml::Value::Array(specs) => match specs.first() {
+            Some(spec) => spec,
+            None => return Ok(None)
+        },
+        spec => spec,
+    };
+    let table = match spec {
+        toml::Value::String(constraint) => {
+            dependency.version = Some(constraint.clone());
+            dependency.registry = Some(String::from(PYPI_REGISTRY));
+            return Ok(Some(dependency));
+        },
+        toml::Value::Table(table) => table,
+        _ => return Ok(None)
+    };
+    let field = |name: &str| table.get(name).and_then(|value| value.as_str()).map(String::from);
+    if let Some(url) = field("git") {
+        let reference = field("branch").or_else(|| field("tag")).or_else(|| field("rev"));
+        dependency.git = Some(GitSource { url, reference });
+        return Ok(Some(dependency));
+    }
+    if let Some(path) = field("path") {
+        return path_dependency(manifest_dir, dependency, &path).map(Some);
+    }
+    if field("url").is_some() {
+        return Ok(None);
+    }
+    dependency.version = Some(field("version").unwrap_or_else(|| String::from("*")));
+    dependency.registry = Some(String::from(PYPI_REGISTRY));
+    Ok(Some(dependency))
+}
+
+/// Points `dependency` at the project in `path`, using the name and version in its
+/// pyproject.toml.
+fn path_dependency(manifest_dir: &Path, dependency: Dependency, path: &str) -> Result<Dependency, Error> {
+    let path: PathBuf = manifest_dir.join(path);
+    let pyproject_path = path.join(MANIFEST_FILE_NAME);
+    let (name, version) = name_and_version(&pyproject_path, &read_pyproject(&pyproject_path)?)?;
+    Ok(Dependency {
+        name,
+        locked_version: Some(version),
+        path: Some(path),
+        ..dependency
+    })
+}
+
+/// Normalizes a project name the way package indexes do, so that "Foo_Bar" and "foo-bar" are the
+/// same package.
+///
+/// https://peps.python.org/pep-0503/#normalized-names
+fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.trim().chars() {
+        if "-_.".contains(c) {
+            if !normalized.ends_with('-') {
+                normalized.push('-');
+            }
+        } else {
+            normalized.push(c.to_ascii_lowercase());
+        }
+    }
+    normalized
+}
+
+/// A parsed PEP 440 version. Local versions ("+ubuntu1") are ignored.
+#[derive(Clone, Debug)]
+struct PythonVersion {
+    epoch: u64,
+    release: Vec<u64>,
+    /// (0 for alpha, 1 for beta or 2 for rc, number)
+    pre: Option<(u8, u64)>,
+    post: Option<u64>,
+    dev: Option<u64>,
+}
+
+impl PythonVersion {
+    fn parse(version: &str) -> Option<PythonVersion> {
+        let captures = version_pattern().captures(version)?;
+        let number = |index: usize| captures.get(index).map(|m| m.as_str().parse::<u64>().unwrap_or(0));
+        let pre = captures.get(3).map(|kind| {
+            let rank = match kind.as_str().to_lowercase().as_str() {
+                "a" | "alpha" => 0,
+                "b" | "beta" => 1,
+                _ => 2,
+            };
+            (rank, number(4).unwrap_or(0))
+        });
+        // "1.0-1" and "1.0.post1" are both post-releases, and "1.0.post" is "1.0.post0".
+        let post = number(5).or_else(|| captures.get(6).map(|_| number(7).unwrap_or(0)));
+        let dev = captures.get(8).map(|_| number(9).unwrap_or(0));
+        Some(PythonVersion {
+            epoch: number(1).unwrap_or(0),
+            release: captures.get(2)?.as_str().split('.').map(|part| part.parse().unwrap_or(0)).collect(),
+            pre,
+            post,
+            dev,
+        })
+    }
+
+    fn is_pre_release(&self) -> bool {
+        self.pre.is_some() || self.dev.is_some()
+    }
+
+    /// The release with trailing zeros removed, so that 1.0 and 1.0.0 compare equal.
+    fn trimmed_release(&self) -> &[u64] {
+        let len = self.release.iter().rposition(|part| *part != 0).map_or(0, |index| index + 1);
+        &self.release[..len]
+    }
+
+    /// The release padded or truncated to `len` parts.
+    fn release_prefix(&self, len: usize) -> Vec<u64> {
+        (0..len).map(|index| self.release.get(index).copied().unwrap_or(0)).collect()
+    }
+}
+
+impl Ord for PythonVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // A dev release of a final version ("1.0.dev1") comes before its pre-releases.
+        let pre_key = |version: &PythonVersion| match (version.pre, version.post, version.dev) {
+            (None, None, Some(_)) => (0, 0, 0),
+            (Some((rank, number)), _, _) => (1, rank, number),
+            _ => (2, 0, 0),
+        };
+        let dev_key = |version: &PythonVersion| (version.dev.is_none(), version.dev.unwrap_or(0));
+        self.epoch.cmp(&other.epoch)
+            .then_with(|| self.trimmed_release().cmp(other.trimmed_release()))
+            .then_with(|| pre_key(self).cmp(&pre_key(other)))
+            .then_with(|| self.post.cmp(&other.post))
+            .then_with(|| dev_key(self).cmp(&dev_key(other)))
+    }
+}
+
+impl PartialEq for PythonVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PythonVersion {}
+
+impl PartialOrd for PythonVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// One clause of a requirement, e.g. ">=1.2" or "==1.2.*".
+struct Specifier {
+    operator: String,
+    /// `None` for "===", which compares strings.
+    version: Option<PythonVersion>,
+    text: String,
+    /// "==1.2.*" and "!=1.2.*" match on the release prefix.
+    wildcard: bool,
+}
+
+impl Specifier {
+    fn parse(clause: &str) -> Option<Specifier> {
+        let version_start = clause.find(|c: char| !"<>=!~^ ".contains(c)).unwrap_or(clause.len());
+        let operator = clause[..version_start].replace(' ', "");
+        // A bare version is exact.
+        let operator = if operator.is_empty() { String::from("==") } else { operator };
+        let text = clause[version_start..].trim().to_string();
+        if !["==", "!=", ">=", "<=", ">", "<", "~=", "===", "^", "~"].contains(&operator.as_str()) {
+            return None;
+        }
+        if operator == "===" {
+            return Some(Specifier { operator, version: None, text, wildcard: false });
+        }
+        let wildcard = text.ends_with(".*");
+        if wildcard && operator != "==" && operator != "!=" {
+            return None;
+        }
+        let version = PythonVersion::parse(text.trim_end_matches(".*"))?;
+        Some(Specifier { operator, version: Some(version), text, wildcard })
+    }
+
+    fn matches(&self, candidate: &PythonVersion, candidate_text: &str) -> bool {
+        let version = match &self.version {
+            Some(version) => version,
+            None => return candidate_text.trim() == self.text,
+        };
+        let prefix_matches = |len: usize| candidate.epoch == version.epoch && candidate.release_prefix(len) == version.release_prefix(len);
+        match self.operator.as_str() {
+            "==" if self.wildcard => prefix_matches(version.release.len()),
+            "!=" if self.wildcard => !prefix_matches(version.release.len()),
+            "==" => candidate == version,
+            "!=" => candidate != version,
+            ">=" => candidate >= version,
+            "<=" => candidate <= version,
+            ">" => candidate > version,
+            "<" => candidate < version,
+            // "~=1.4.2" is ">=1.4.2, ==1.4.*".
+            "~=" => candidate >= version && prefix_matches(version.release.len().saturating_sub(1).max(1)),
+            // Poetry: "^1.2.3" allows changes that don't modify the leftmost non-zero part.
+            "^" => {
+                let len = version.release.iter().position(|part| *part != 0).map_or(version.release.len(), |index| index + 1);
+                candidate >= version && prefix_matches(len)
+            },
+            // Poetry: "~1.2.3" allows patch changes, "~1" minor ones.
+            "~" => candidate >= version && prefix_matches(version.release.len().min(2)),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_project;
+
+    fn matching(requirement: &str, versions: &[&str]) -> Option<Vec<String>> {
+        let package = PythonPackage { name: String::from("consumer"), version: String::from("1.0"), dependencies: vec![] };
+        let versions: Vec<String> = versions.iter().map(|version| version.to_string()).collect();
+        package.matching_versions(requirement, &versions)
+    }
+
+    fn parse(version: &str) -> PythonVersion {
+        PythonVersion::parse(version).unwrap()
+    }
+
+    #[test]
+    fn versions_are_ordered_by_pep_440() {
+        let ordered = ["1.0.dev1", "1.0a1", "1.0b2", "1.0rc1", "1.0", "1.0.post1", "1.1", "1!0.1"];
+        for pair in ordered.windows(2) {
+            assert!(parse(pair[0]) < parse(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(parse("1.0"), parse("1.0.0"));
+        assert_eq!(parse("1.0-1"), parse("1.0.post1"));
+        assert_eq!(parse("1.0+ubuntu1"), parse("1.0"));
+    }
+
+    #[test]
+    fn matches_comparison_and_exclusion_specifiers() {
+        let versions = ["1.0", "1.4.2", "1.5", "2.0", "2.1rc1"];
+        assert_eq!(matching(">=1.4,<2", &versions).unwrap(), ["1.4.2", "1.5"]);
+        assert_eq!(matching("!=1.5,>1.0", &versions).unwrap(), ["1.4.2", "2.0"]);
+        assert_eq!(matching("==1.*", &versions).unwrap(), ["1.0", "1.4.2", "1.5"]);
+        assert_eq!(matching("1.5", &versions).unwrap(), ["1.5"]);
+        assert_eq!(matching("*", &versions).unwrap(), ["1.0", "1.4.2", "1.5", "2.0"]);
+    }
+
+    #[test]
+    fn compatible_release_stays_within_the_prefix() {
+        let versions = ["1.4.1", "1.4.2", "1.4.9", "1.5.0", "2.0"];
+        assert_eq!(matching("~=1.4.2", &versions).unwrap(), ["1.4.2", "1.4.9"]);
+        assert_eq!(matching("~=1.4", &versions).unwrap(), ["1.4.1", "1.4.2", "1.4.9", "1.5.0"]);
+    }
+
+    #[test]
+    fn matches_poetry_caret_tilde_and_alternatives() {
+        let versions = ["0.2.1", "0.3.0", "1.2.3", "1.3.0", "2.0.0"];
+        assert_eq!(matching("^1.2.3", &versions).unwrap(), ["1.2.3", "1.3.0"]);
+        assert_eq!(matching("^0.2.1", &versions).unwrap(), ["0.2.1"]);
+        assert_eq!(matching("~1.2.3", &versions).unwrap(), ["1.2.3"]);
+        assert_eq!(matching("^0.2 || ^2.0", &versions).unwrap(), ["0.2.1", "2.0.0"]);
+    }
+
+    #[test]
+    fn pre_releases_only_match_when_requested() {
+        let versions = ["1.0", "1.1rc1", "1.1"];
+        assert_eq!(matching(">=1.0", &versions).unwrap(), ["1.0", "1.1"]);
+        assert_eq!(matching(">=1.1rc1", &versions).unwrap(), ["1.1rc1", "1.1"]);
+    }
+
+    #[test]
+    fn unknown_specifiers_are_not_understood() {
+        assert!(matching("=>1.0", &["1.0"]).is_none());
+        assert!(matching(">=1.*", &["1.0"]).is_none());
+    }
+
+    #[test]
+    fn reads_requirements_and_pins() {
+        let dir = temp_project("python", &[
+            ("pyproject.toml", r#"
+                [project]
+                name = "My_App"
+                version = "0.3.0"
+                dependencies = ["Requests[socks]>=2.8; python_version >= '3.8'", "lib @ git+https://host/lib.git@v1"]
+            "#),
+            ("requirements.txt", "requests==2.31.0  # pinned\n-r other.txt\nurllib3==2.0.*\n"),
+        ]);
+        let package = PythonPackage::from_path(dir.join("pyproject.toml")).unwrap();
+        assert_eq!(package.name(), "my-app");
+        let dependencies: Vec<(&str, Option<&str>, Option<&str>)> = package.dependencies().iter()
+            .map(|dependency| (dependency.name.as_str(), dependency.version.as_deref(), dependency.locked_version.as_deref()))
+            .collect();
+        assert_eq!(dependencies, [
+            ("requests", Some(">=2.8"), Some("2.31.0")),
+            ("lib", None, None),
+            ("urllib3", Some("==2.0.*"), None),
+        ]);
+        let git = package.dependencies()[1].git.as_ref().unwrap();
+        assert_eq!((git.url.as_str(), git.reference.as_deref()), ("https://host/lib.git", Some("v1")));
+    }
+
+    #[test]
+    fn reads_a_requirements_file_through_the_pyproject_beside_it() {
+        let dir = temp_project("python-requirements", &[
+            ("pyproject.toml", "[project]\nname = \"app\"\nversion = \"1.0.0\"\n"),
+            ("requirements.txt", "requests==2.31.0\n"),
+        ]);
+        let package = PythonPackage::from_path(dir.join("requirements.txt")).unwrap();
+        assert_eq!((package.name(), package.version()), (String::from("app"), String::from("1.0.0")));
+        assert_eq!(package.dependencies()[0].locked_version.as_deref(), Some("2.31.0"));
+    }
+
+    #[test]
+    fn requires_a_pyproject_to_name_the_project() {
+        let dir = temp_project("python-requirements-only", &[("requirements.txt", "requests==2.31.0\n")]);
+        match PythonPackage::from_path(dir.join("requirements.txt")) {
+            Err(Error::InvalidManifest { reason, .. }) => assert!(reason.contains("pyproject.toml"), "{}", reason),
+            other => panic!("Expected an invalid manifest, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -1,130 +1,219 @@
-use std::collections::{HashMap, HashSet};
-use aws_config::Config;
-use aws_sdk_codebuild::{Client as CodeBuildClient, SdkError};
-use aws_sdk_codebuild::error::StartBuildErrorKind;
-use aws_sdk_dynamodb::{Client as DynamoDbClient, SdkError as DynamoDbError};
-use aws_sdk_dynamodb::model::{AttributeAction, AttributeValue, AttributeValueUpdate, ReturnValue};
-use futures::future::try_join_all;
-use once_cell::sync::OnceCell;
-use regex::Regex;
-use crate::{CrateHelper, crate_helper};
-use crate::crate_helper::{Dependency, Error};
-
-const BUILD_SYSTEM: &str = "rust";
-
-const KEY_CODE_BUILD_PROJECT_NAME: &str = "code_build_project_name";
-const KEY_PACKAGE_NAME: &str = "package_name";
-const KEY_VERSION: &str = "version";
-const KEY_CONSUMERS: &str = "consumers";
-const KEY_DEPENDENCIES: &str = "dependencies";
-const KEY_BUILD_SYSTEM_AND_NAME_DELIMITER: &str = "/";
-const KEY_NAME_AND_VERSION_DELIMITER: &str = ":";
-
-const PKG_KEY_REGEX: &str = "(.+)/(.+):(.+)";
-
-fn package_key_pattern() -> &'static Regex {
-    static INSTANCE: OnceCell<Regex> = OnceCell::new();
-    INSTANCE.get_or_init(|| Regex::new(PKG_KEY_REGEX).unwrap())
-}
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use futures::future::{join_all, try_join_all};
+use tokio::time::{self, Instant};
+use crate::error::Error;
+use crate::graph::{self, ConsumerGraph, PackageGraph};
+use crate::manifest::{self, Dependency, DependencyKind, GitSource, ManifestReader};
+use crate::package_key::PackageKey;
+use crate::store::{MetadataStore, PackageRecord, PackageUpdate};
+use crate::trigger::{BuildStatus, BuildTarget, BuildTriggers, TriggerKind};
 
+/// How the package being registered is rebuilt.
 pub struct BuildDetails {
-    pub build_project_name: String
+    pub build_target: BuildTarget,
+    /// The repository refs the build was made from. Each is registered as a git key with the
+    /// same build target, so that consumers depending on the repository through git are tracked.
+    pub sources: Vec<GitSource>,
 }
 
-#[derive(Debug)]
-pub struct PackageKey {
-    pub build_system: String,
-    pub name: String,
-    pub version: String,
+const MATCHING_ALL: &str = "all";
+const MATCHING_HIGHEST: &str = "highest";
+
+/// Which tracked versions of a dependency a consumer is linked to when its requirement matches
+/// more than one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VersionMatching {
+    /// Every matching version, so that a change to any of them rebuilds the consumer.
+    All,
+    /// Only the highest matching version, which is the one Cargo and npm would pick.
+    Highest,
 }
 
-impl PackageKey {
-    pub fn from_fq_key(fq_key: &String) -> Result<PackageKey, Error> {
-        match package_key_pattern().captures(fq_key) {
-            Some(match_elements) => {
-                let build_system = String::from(match_elements.get(1).map(|m| m.as_str()).expect("Expected build system to be present"));
-                let name = String::from(match_elements.get(2).map(|m| m.as_str()).expect("Expected package name to be present."));
-                let version = String::from(match_elements.get(3).map(|m| m.as_str()).expect("Expected version to be present."));
-                Ok(PackageKey {
-                    build_system,
-                    name,
-                    version,
-                })
-            },
-            None => Err(Error::with_msg(format!("Fully qualified key \"{}\" is in unexpected format.", fq_key)))
+impl fmt::Display for VersionMatching {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionMatching::All => f.write_str(MATCHING_ALL),
+            VersionMatching::Highest => f.write_str(MATCHING_HIGHEST),
         }
     }
+}
 
-    pub fn to_fq_key(&self) -> String {
-        format!("{}{}{}{}{}", self.build_system, KEY_BUILD_SYSTEM_AND_NAME_DELIMITER, self.name, KEY_NAME_AND_VERSION_DELIMITER, self.version)
-    }
+impl FromStr for VersionMatching {
+    type Err = String;
 
-    fn ddb_primary_key(&self) -> HashMap<String, AttributeValue> {
-        let mut key: HashMap<String, AttributeValue> = HashMap::new();
-        key.insert(String::from(KEY_PACKAGE_NAME), AttributeValue::S(format!("{}/{}", self.build_system, self.name)));
-        key.insert(String::from(KEY_VERSION), AttributeValue::S(self.version.clone()));
-        key
+    fn from_str(matching: &str) -> Result<Self, Self::Err> {
+        match matching {
+            MATCHING_ALL => Ok(VersionMatching::All),
+            MATCHING_HIGHEST => Ok(VersionMatching::Highest),
+            _ => Err(format!("Unknown version matching \"{}\". Expected one of: {}, {}", matching, MATCHING_ALL, MATCHING_HIGHEST)),
+        }
     }
 }
 
-impl From<CrateHelper> for PackageKey {
-    fn from(crate_helper: CrateHelper) -> Self {
-        PackageKey {
-            build_system: String::from(BUILD_SYSTEM),
-            name: crate_helper.name(),
-            version: crate_helper.version(),
-        }
-    }
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(3600);
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long to wait for the rebuilds the updater starts, and what to do when one fails.
+#[derive(Clone, Debug)]
+pub struct WaitPolicy {
+    /// How long all of the rebuilds together may take, counted from when the first is started.
+    pub timeout: Duration,
+    /// How long to sleep between checks of the rebuilds' statuses.
+    pub poll_interval: Duration,
+    /// Stop once a wave has a failed rebuild instead of starting the waves after it.
+    pub abort_on_failure: bool,
 }
 
-fn to_set(vec: &Vec<String>) -> HashSet<String> {
-    let mut set: HashSet<String> = HashSet::new();
-    for element in vec {
-        set.insert(element.clone());
+impl Default for WaitPolicy {
+    fn default() -> Self {
+        WaitPolicy {
+            timeout: DEFAULT_WAIT_TIMEOUT,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            abort_on_failure: false,
+        }
     }
-    set
 }
 
-fn get_encoded_primary_key(name: &String, version: &String) -> String {
-    format!("{}/{}{}{}", BUILD_SYSTEM, name, KEY_NAME_AND_VERSION_DELIMITER, version)
+/// A rebuild that was started, and the trigger to ask about its status.
+struct StartedBuild {
+    consumer: String,
+    kind: TriggerKind,
+    build_id: Option<String>,
 }
 
+/// Registers packages and coordinates rebuilds of their consumers through a [`MetadataStore`]
+/// and a set of [`BuildTriggers`].
 pub struct CrateMetadataUpdater {
-    ddb: DynamoDbClient,
-    codebuild: CodeBuildClient,
-    pkg_metadata_table: String,
+    store: Arc<dyn MetadataStore>,
+    triggers: BuildTriggers,
+    version_matching: VersionMatching,
+    rebuild_kinds: BTreeSet<DependencyKind>,
+    tracked_registries: HashSet<String>,
+    package_version: Option<String>,
+    transitive: bool,
+    wait_policy: Option<WaitPolicy>,
+    skip_consumer_rebuilds: bool,
 }
 
 impl CrateMetadataUpdater {
-    pub fn new(client_config: &Config, pkg_metadata_table: String) -> CrateMetadataUpdater {
+    pub fn new(store: Arc<dyn MetadataStore>, triggers: BuildTriggers) -> CrateMetadataUpdater {
         CrateMetadataUpdater {
-            ddb: DynamoDbClient::new(client_config),
-            codebuild: CodeBuildClient::new(client_config),
-            pkg_metadata_table,
+            store,
+            triggers,
+            version_matching: VersionMatching::All,
+            rebuild_kinds: BTreeSet::from(DependencyKind::ALL),
+            tracked_registries: HashSet::new(),
+            package_version: None,
+            transitive: false,
+            wait_policy: None,
+            skip_consumer_rebuilds: false,
         }
     }
 
-    pub async fn update_metadata(self, build_details: BuildDetails, path: String) -> Result<(), crate_helper::Error> {
-        let crt = match CrateHelper::from_path(path) {
-            Ok(crt) => crt,
-            Err(err) => return Err(err)
-        };
+    /// Waits for started rebuilds to finish before returning, and for each wave to finish before
+    /// starting the next. Rebuilds are started and left to run by default, unless they're
+    /// [transitive](CrateMetadataUpdater::with_transitive).
+    pub fn with_wait_policy(mut self, wait_policy: Option<WaitPolicy>) -> CrateMetadataUpdater {
+        self.wait_policy = wait_policy;
+        self
+    }
+
+    /// Rebuilds consumers of consumers too, in waves that start once the packages they depend on
+    /// have been rebuilt (see [`ConsumerGraph::waves`]). Waves are always waited for, with the
+    /// default [`WaitPolicy`] if none is set, and the rebuilds are told not to rebuild their own
+    /// consumers. Only direct consumers are rebuilt by default, leaving the rest to their own
+    /// builds.
+    pub fn with_transitive(mut self, transitive: bool) -> CrateMetadataUpdater {
+        self.transitive = transitive;
+        self
+    }
+
+    /// Registers packages without rebuilding their consumers, for builds started by a transitive
+    /// rebuild that's already rebuilding them.
+    pub fn with_skip_consumer_rebuilds(mut self, skip_consumer_rebuilds: bool) -> CrateMetadataUpdater {
+        self.skip_consumer_rebuilds = skip_consumer_rebuilds;
+        self
+    }
+
+    /// Sets the version registered for packages whose manifest doesn't have one, such as go.mod,
+    /// which otherwise comes from a VERSION file beside the manifest.
+    pub fn with_package_version(mut self, package_version: Option<String>) -> CrateMetadataUpdater {
+        self.package_version = package_version;
+        self
+    }
+
+    /// Limits tracking to dependencies from these registries (by name, index URL, "crates-io" or
+    /// "npmjs"), so that dependencies from any other registry are skipped without looking them
+    /// up. Git and path dependencies are always tracked. Every registry is tracked if the set is
+    /// empty, which is the default.
+    pub fn with_tracked_registries(mut self, tracked_registries: HashSet<String>) -> CrateMetadataUpdater {
+        self.tracked_registries = tracked_registries;
+        self
+    }
 
+    /// Sets which kinds of dependency trigger rebuilds of their consumers. Defaults to every kind.
+    pub fn with_rebuild_kinds(mut self, rebuild_kinds: BTreeSet<DependencyKind>) -> CrateMetadataUpdater {
+        self.rebuild_kinds = rebuild_kinds;
+        self
+    }
+
+    /// Sets which tracked versions of a dependency a consumer is linked to when its requirement
+    /// matches more than one. Defaults to [`VersionMatching::All`].
+    pub fn with_version_matching(mut self, version_matching: VersionMatching) -> CrateMetadataUpdater {
+        self.version_matching = version_matching;
+        self
+    }
+
+    /// Registers the packages whose manifests are at `manifests` (see
+    /// [`manifest::detect_manifests`]): records each as a consumer of its tracked dependencies,
+    /// stores its record, then rebuilds the consumers of its previous record.
+    ///
+    /// Every package is registered against the same build. A Cargo workspace root registers each
+    /// member, with members registered after the members they depend on. Consumers that are among
+    /// the registered packages aren't rebuilt, since they're part of the build that's registering
+    /// them.
+    pub async fn update_metadata(&self, build_details: BuildDetails, manifests: &[PathBuf]) -> Result<(), Error> {
+        let mut members = vec![];
+        for path in manifests {
+            members.extend(manifest::read_packages(path, self.package_version.as_deref())?);
+        }
+        let built_together: HashSet<String> = members.iter()
+            .map(|member| PackageKey::from(member.as_ref()).qualified_name())
+            .collect();
+        for crt in members {
+            self.register_crate(&build_details, crt, &built_together).await?;
+        }
+        for source in &build_details.sources {
+            let git_key = PackageKey::for_git(&source.url, source.reference.as_deref());
+            eprintln!("Registering {}.", git_key.to_fq_key());
+            self.update_project(&git_key, &build_details, BTreeMap::new(), &built_together).await?;
+        }
+        Ok(())
+    }
+
+    async fn register_crate(&self, build_details: &BuildDetails, crt: Box<dyn ManifestReader>, built_together: &HashSet<String>) -> Result<(), Error> {
+        eprintln!("Registering {}/{}:{}.", crt.build_system(), crt.name(), crt.version());
         // https://docs.rs/futures/latest/futures/future/fn.try_join_all.html
         // https://users.rust-lang.org/t/how-to-execute-multiple-async-fns-at-once-and-use-join-all-to-get-all-their-results/47437/4
         let mut dep_update_futures = vec![];
         // First update those dependencies that are being tracked with this crate as a consumer.
         // This allows us to then store only those dependencies that are tracked.
-        for dep in &crt.dependencies {
-            dep_update_futures.push(Box::pin(self.add_consumer_to_dependency(&crt, dep)));
+        for dep in crt.dependencies() {
+            dep_update_futures.push(Box::pin(self.add_consumer_to_dependency(crt.as_ref(), dep)));
         }
 
         let tracked_deps = match try_join_all(dep_update_futures).await {
             Ok(deps) => {
-                let mut tracked_deps = vec![];
-                for dep in deps {
-                    if let Some(tracked_dep) = dep {
-                        tracked_deps.push(tracked_dep);
+                // The same dependency can be declared in several sections, so gather its kinds.
+                let mut tracked_deps: BTreeMap<String, BTreeSet<DependencyKind>> = BTreeMap::new();
+                for (dep, linked_deps) in crt.dependencies().iter().zip(deps) {
+                    for linked_dep in linked_deps {
+                        tracked_deps.entry(linked_dep).or_default().insert(dep.kind);
                     }
                 }
                 tracked_deps
@@ -132,229 +221,640 @@ impl CrateMetadataUpdater {
             Err(err) => return Err(err)
         };
 
-        let pkg_key = PackageKey::from(crt);
-        match self.update_project(&pkg_key, &build_details, tracked_deps).await {
+        let pkg_key = PackageKey::from(crt.as_ref());
+        match self.update_project(&pkg_key, build_details, tracked_deps, built_together).await {
             Ok(_) => Ok(()),
-            Err(err) => return Err(err)
+            Err(err) => Err(err)
         }
     }
 
-    async fn add_consumer_to_dependency(&self, crt: &CrateHelper, dep: &Dependency) -> Result<Option<String>, crate_helper::Error> {
-        // TODO: Need to update all dependencies that match the version pattern.
-        // ...or just the latest that matches the pattern?
-        // When adding a consumer, it needs to be added to all matching versions.
-        // NOTE: This probably isn't entirely true, but makes things a bit easier.
-        // Crate: https://docs.rs/semver/latest/semver/index.html
-        // https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html
-
-        let consumer_key = get_encoded_primary_key(&crt.name(), &crt.version());
-        match &dep.version {
-            Some(version) => {
-                // If a record for this dependency exists, then add the current crate as a consumer
-                // of it.
-                let fq_dep_name = get_encoded_primary_key(&dep.name, &version);
-                let dep_key = PackageKey {
-                    build_system: String::from(BUILD_SYSTEM),
-                    name: dep.name.clone(),
-                    version: version.clone(),
-                };
-                match self.ddb.update_item()
-                    .table_name(self.pkg_metadata_table.clone())
-                    .set_key(Some(dep_key.ddb_primary_key()))
-                    .update_expression(format!("ADD {} :d", KEY_CONSUMERS))
-                    .expression_attribute_values(":d", AttributeValue::Ss(vec![consumer_key.clone()]))
-                    .condition_expression(format!("attribute_exists({})", KEY_PACKAGE_NAME)).send().await {
-                    Ok(_) => {
-                        log::info!("{} added as consumer of {}.", consumer_key, dep.name);
-                        Ok(Some(fq_dep_name))
-                    },
-                    Err(err) => {
-                        match err {
-                            DynamoDbError::ServiceError {err, ..} => {
-                                if err.is_conditional_check_failed_exception() {
-                                    eprintln!("{} not being tracked. Skipping...", fq_dep_name);
-                                    Ok(None)
-                                } else {
-                                    return Err(crate_helper::Error::with_msg(format!("ERROR: {}", err.to_string())))
-                                }
-                            },
-                            _ => return Err(crate_helper::Error::with_msg(format!("ERROR: {}", err.to_string())))
-                        }
-                    }
-                }
+    /// Fetches the stored record for a package, if it's being tracked.
+    pub async fn get_package(&self, pkg_key: &PackageKey) -> Result<Option<PackageRecord>, Error> {
+        self.store.get_record(pkg_key).await
+    }
+
+    /// Lists every tracked version of the package, regardless of the version in `pkg_key`.
+    pub async fn list_versions(&self, pkg_key: &PackageKey) -> Result<Vec<String>, Error> {
+        self.store.list_versions(&pkg_key.build_system, &pkg_key.name).await
+    }
+
+    /// Rebuilds every consumer of a package without touching the package's own record.
+    pub async fn rebuild_consumers(&self, pkg_key: &PackageKey) -> Result<(), Error> {
+        match self.store.get_record(pkg_key).await? {
+            Some(record) if self.transitive => self.rebuild_waves(pkg_key, &record.consumers, &HashSet::new()).await,
+            Some(record) => {
+                let consumers: Vec<String> = record.consumers.into_iter().collect();
+                self.rebuild_all(pkg_key, &consumers).await
+            },
+            None => Err(Error::NotTracked(pkg_key.to_fq_key()))
+        }
+    }
+
+    /// Finds every cycle of consumers among the tracked packages (see [`graph::find_cycles`]),
+    /// following only the dependency kinds that trigger rebuilds.
+    pub async fn find_cycles(&self) -> Result<Vec<Vec<String>>, Error> {
+        Ok(graph::find_cycles(&self.store.list_records().await?, &self.rebuild_kinds))
+    }
+
+    /// Every tracked package and the edges between them.
+    pub async fn package_graph(&self) -> Result<PackageGraph, Error> {
+        Ok(PackageGraph::from_records(self.store.list_records().await?))
+    }
+
+    /// Deletes a package's record and removes every edge that refers to it, so that neither its
+    /// dependencies nor its consumers point at it anymore.
+    pub async fn forget_package(&self, pkg_key: &PackageKey) -> Result<(), Error> {
+        let record = match self.store.delete_record(pkg_key).await? {
+            Some(record) => record,
+            None => {
+                eprintln!("{} not being tracked. Nothing to forget.", pkg_key.to_fq_key());
+                return Ok(());
+            }
+        };
+
+        let mut dep_rm_futures = vec![];
+        for dependency in &record.dependencies {
+            dep_rm_futures.push(Box::pin(self.rm_consumer_from_dependency(pkg_key, PackageKey::from_fq_key(dependency)?)));
+        }
+        try_join_all(dep_rm_futures).await?;
+
+        let mut consumer_rm_futures = vec![];
+        for consumer in &record.consumers {
+            consumer_rm_futures.push(Box::pin(self.rm_dependency_from_consumer(pkg_key, PackageKey::from_fq_key(consumer)?)));
+        }
+        try_join_all(consumer_rm_futures).await?;
+        eprintln!("Forgot {}.", pkg_key.to_fq_key());
+        Ok(())
+    }
+
+    /// Adds the package as a consumer of every tracked version of `dep` it could be built against,
+    /// returning the keys of the versions it was linked to.
+    ///
+    /// Git dependencies are linked to the key of their repository and ref. A version locked by a
+    /// lock file or found at a dependency's path is linked on its own. Otherwise the requirement
+    /// is matched against every tracked version of the dependency, using the package's build
+    /// system's rules.
+    async fn add_consumer_to_dependency(&self, crt: &dyn ManifestReader, dep: &Dependency) -> Result<Vec<String>, Error> {
+        if let Some(registry) = &dep.registry {
+            if !self.tracked_registries.is_empty() && !self.tracked_registries.contains(registry) {
+                log::info!("{} comes from untracked registry {}. Skipping...", dep.name, registry);
+                return Ok(vec![]);
             }
+        }
+        let consumer_key = PackageKey::from(crt);
+        if let Some(git) = &dep.git {
+            let git_key = PackageKey::for_git(&git.url, git.reference.as_deref());
+            return Ok(self.link_dependency(&consumer_key, git_key).await?.into_iter().collect());
+        }
+        let dep_key = |version: &str| PackageKey {
+            build_system: String::from(crt.build_system()),
+            name: dep.name.clone(),
+            version: version.to_string(),
+        };
+        if let Some(exact_version) = &dep.locked_version {
+            return Ok(self.link_dependency(&consumer_key, dep_key(exact_version)).await?.into_iter().collect());
+        }
+        let requirement = match &dep.version {
+            Some(requirement) => requirement,
             None => {
-                log::error!("Crate {} doesn't have a version specified.", dep.name);
-                Ok(None)
+                log::error!("Package {} doesn't have a version specified.", dep.name);
+                return Ok(vec![]);
             }
+        };
+        let tracked_versions = self.store.list_versions(crt.build_system(), &dep.name).await?;
+        let mut matching_versions = match crt.matching_versions(requirement, &tracked_versions) {
+            Some(matching_versions) => matching_versions,
+            // Not a requirement the build system understands, so the best we can do is treat it
+            // as an exact version.
+            None => return Ok(self.link_dependency(&consumer_key, dep_key(requirement)).await?.into_iter().collect())
+        };
+        if self.version_matching == VersionMatching::Highest {
+            matching_versions = matching_versions.pop().into_iter().collect();
+        }
+
+        let mut linked_deps = vec![];
+        for version in &matching_versions {
+            if let Some(fq_dep_name) = self.link_dependency(&consumer_key, dep_key(version)).await? {
+                linked_deps.push(fq_dep_name);
+            }
+        }
+        if linked_deps.is_empty() {
+            eprintln!("No tracked version of {}/{} matches {}. Skipping...", crt.build_system(), dep.name, requirement);
+        } else {
+            eprintln!("{} linked to {} {}: {}", consumer_key.to_fq_key(), dep.name, requirement, linked_deps.join(", "));
+        }
+        Ok(linked_deps)
+    }
+
+    /// Adds `consumer_key` as a consumer of `dep_key`, if it's being tracked. Returns the
+    /// dependency's key if it was linked.
+    async fn link_dependency(&self, consumer_key: &PackageKey, dep_key: PackageKey) -> Result<Option<String>, Error> {
+        // If a record for this dependency exists, then add the current crate as a consumer
+        // of it.
+        let fq_dep_name = dep_key.to_fq_key();
+        if self.store.add_consumer(&dep_key, consumer_key).await? {
+            log::info!("{} added as consumer of {}.", consumer_key.to_fq_key(), fq_dep_name);
+            Ok(Some(fq_dep_name))
+        } else {
+            eprintln!("{} not being tracked. Skipping...", fq_dep_name);
+            Ok(None)
         }
     }
 
-    async fn update_project(&self, pkg_key: &PackageKey, build_details: &BuildDetails, tracked_deps: Vec<String>) -> Result<(), crate_helper::Error> {
+    async fn update_project(&self, pkg_key: &PackageKey, build_details: &BuildDetails, tracked_deps: BTreeMap<String, BTreeSet<DependencyKind>>, built_together: &HashSet<String>) -> Result<(), Error> {
         // We won't (and shouldn't) try and rebuild all projects that would consume a new version as
         // the actual versions being used by the consumer should be locked, until it's rebuilt, at
         // which point, it will grab the appropriate version and add itself as a consumer to that
         // version.
         // Also, the single CodeBuild project per codebase doesn't work if multiple versions of the
         // package are active. For example, v1 and v2 and applying patches to both versions.
-        let tracked_deps_set = to_set(&tracked_deps);
-        let dep_attribute_update = if tracked_deps.is_empty() {
-            // If tracked_deps is empty, delete the dependencies value.
-            AttributeValueUpdate::builder()
-                .action(AttributeAction::Delete)
-        } else {
-            AttributeValueUpdate::builder()
-                .value(AttributeValue::Ss(tracked_deps))
-        }.build();
-
         // TODO: Don't update project name if local build is being executed (project name is "local")
-        match self.ddb.update_item()
-            .table_name(self.pkg_metadata_table.clone())
-            .set_key(Some(pkg_key.ddb_primary_key()))
-            .attribute_updates(KEY_CODE_BUILD_PROJECT_NAME,
-                               AttributeValueUpdate::builder()
-                                   .value(AttributeValue::S(build_details.build_project_name.clone()))
-                                   .build())
-            .attribute_updates(KEY_DEPENDENCIES, dep_attribute_update)
-            .return_values(ReturnValue::AllOld)
-            .send().await {
-            Ok(response) => {
-                if let Some(old_attributes) = response.attributes {
-                    if let Some(old_deps_av) = old_attributes.get(KEY_DEPENDENCIES) {
-                        if let Ok(old_deps) = old_deps_av.as_ss() {
-                            let old_deps_set = to_set(old_deps);
-                            // Clean up old dependencies that should no longer exist.
-                            let mut dep_rm_futures = vec![];
-                            for old_dep in old_deps_set.difference(&tracked_deps_set) {
-                                match PackageKey::from_fq_key(&old_dep) {
-                                    Ok(old_dep_key) => dep_rm_futures.push(Box::pin(self.rm_consumer_from_dependency(&pkg_key, old_dep_key))),
-                                    Err(err) => return Err(err),
-                                }
-                            }
-                            if !dep_rm_futures.is_empty() {
-                                match try_join_all(dep_rm_futures).await {
-                                    Ok(_) => (),
-                                    Err(err) => return Err(err)
-                                }
-                            }
-                        }
+        let update = PackageUpdate {
+            build_target: build_details.build_target.clone(),
+            dependencies: tracked_deps.clone(),
+        };
+        if let Some(old_record) = self.store.upsert_package(pkg_key, update).await? {
+            // Clean up old dependencies that should no longer exist.
+            let mut dep_rm_futures = vec![];
+            for old_dep in old_record.dependencies.iter().filter(|old_dep| !tracked_deps.contains_key(*old_dep)) {
+                match PackageKey::from_fq_key(old_dep) {
+                    Ok(old_dep_key) => dep_rm_futures.push(Box::pin(self.rm_consumer_from_dependency(pkg_key, old_dep_key))),
+                    Err(err) => return Err(err),
+                }
+            }
+            if !dep_rm_futures.is_empty() {
+                match try_join_all(dep_rm_futures).await {
+                    Ok(_) => (),
+                    Err(err) => return Err(err)
+                }
+            }
+            if self.skip_consumer_rebuilds {
+                eprintln!("Skipping rebuilds of the consumers of {}, which the build that started this one is rebuilding.", pkg_key.to_fq_key());
+            } else if self.transitive {
+                self.rebuild_waves(pkg_key, &old_record.consumers, built_together).await?;
+            } else if !old_record.consumers.is_empty() {
+                eprintln!("Consumers: {:?}", old_record.consumers);
+                let mut consumers: Vec<String> = vec![];
+                for consumer in old_record.consumers {
+                    if built_together.contains(&PackageKey::from_fq_key(&consumer)?.qualified_name()) {
+                        eprintln!("{} is part of this build. Skipping rebuild...", consumer);
+                    } else {
+                        consumers.push(consumer);
                     }
-                    if let Some(consumers_av) = old_attributes.get(KEY_CONSUMERS) {
-                        if let Ok(consumers) = consumers_av.as_ss() {
-                            println!("Consumers: {:?}", consumers_av);
-                            let mut project_build_futures = vec![];
-                            for fq_consumer_key in consumers {
-                                let consumer_key = PackageKey::from_fq_key(&fq_consumer_key)?;
-                                project_build_futures.push(Box::pin(self.rebuild_consumer(&pkg_key, consumer_key)));
-                            }
-                            match try_join_all(project_build_futures).await {
-                                Ok(_) => (),
-                                Err(err) => return Err(err),
-                            }
-                        }
+                }
+                self.rebuild_all(pkg_key, &consumers).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn rebuild_all(&self, pkg_key: &PackageKey, consumers: &[String]) -> Result<(), Error> {
+        let deadline = self.deadline();
+        let mut project_build_futures = vec![];
+        for fq_consumer_key in consumers {
+            let consumer_key = PackageKey::from_fq_key(fq_consumer_key)?;
+            project_build_futures.push(Box::pin(self.rebuild_consumer(pkg_key, consumer_key)));
+        }
+        let (started, mut failures) = started_builds(consumers, join_all(project_build_futures).await);
+        failures.extend(self.wait_for(started, deadline).await?);
+        if !failures.is_empty() {
+            return Err(Error::RebuildsFailed(failures.join(", ")));
+        }
+        Ok(())
+    }
+
+    /// Rebuilds every consumer that's reached from `pkg_key` through its `consumers`, one wave
+    /// at a time. If the consumers form a cycle, only those outside of it that don't consume it
+    /// are rebuilt, and then the cycle is reported.
+    async fn rebuild_waves(&self, pkg_key: &PackageKey, consumers: &BTreeSet<String>, built_together: &HashSet<String>) -> Result<(), Error> {
+        let graph = ConsumerGraph::walk(self.store.as_ref(), pkg_key, consumers, &self.rebuild_kinds, built_together).await?;
+        let cycle = graph.cycle();
+        if let Some(cycle) = &cycle {
+            log::warn!("Consumers form a cycle: {}. Only rebuilding the consumers that don't depend on it...", cycle.join(" -> "));
+        }
+        let waves = graph.waves();
+        let deadline = self.deadline();
+        let mut failures = vec![];
+        for (index, wave) in waves.iter().enumerate() {
+            log::info!("Rebuilding wave {} of {}: {}", index + 1, waves.len(), wave.join(", "));
+            let mut rebuilt = vec![];
+            let mut project_build_futures = vec![];
+            for fq_consumer_key in wave {
+                let record = graph.record(fq_consumer_key).expect("waves only hold consumers in the graph");
+                let upstream = graph.upstream(fq_consumer_key).expect("consumers in the graph were reached from an upstream");
+                let upstream_key = PackageKey::from_fq_key(upstream)?;
+                rebuilt.push(fq_consumer_key.clone());
+                project_build_futures.push(Box::pin(async move { self.start_rebuild(&upstream_key, record).await }));
+            }
+            let (started, trigger_failures) = started_builds(&rebuilt, join_all(project_build_futures).await);
+            failures.extend(trigger_failures);
+            failures.extend(self.wait_for(started, deadline).await?);
+            if !failures.is_empty() && self.wait_policy().is_some_and(|wait_policy| wait_policy.abort_on_failure) {
+                log::warn!("Aborting after wave {} of {}.", index + 1, waves.len());
+                break;
+            }
+        }
+        if !failures.is_empty() {
+            return Err(Error::RebuildsFailed(failures.join(", ")));
+        }
+        match cycle {
+            Some(cycle) => Err(Error::DependencyCycle(cycle)),
+            None => Ok(())
+        }
+    }
+
+    /// How started rebuilds are waited for, if they are. Transitive rebuilds are always waited
+    /// for, since a wave can't start before the one before it is done.
+    fn wait_policy(&self) -> Option<WaitPolicy> {
+        self.wait_policy.clone().or_else(|| self.transitive.then(WaitPolicy::default))
+    }
+
+    /// When waiting for rebuilds that start now has to stop.
+    fn deadline(&self) -> Option<Instant> {
+        self.wait_policy().map(|wait_policy| Instant::now() + wait_policy.timeout)
+    }
+
+    /// Polls the started rebuilds until every one of them has finished, if there's a wait policy.
+    /// Returns those that didn't succeed. Rebuilds without a build id finished when they were
+    /// started.
+    async fn wait_for(&self, started: Vec<StartedBuild>, deadline: Option<Instant>) -> Result<Vec<String>, Error> {
+        let (wait_policy, deadline) = match (self.wait_policy(), deadline) {
+            (Some(wait_policy), Some(deadline)) => (wait_policy, deadline),
+            _ => return Ok(vec![])
+        };
+        let mut pending: Vec<StartedBuild> = started.into_iter().filter(|build| build.build_id.is_some()).collect();
+        let mut failures = vec![];
+        loop {
+            let mut build_ids: HashMap<TriggerKind, Vec<String>> = HashMap::new();
+            for build in &pending {
+                build_ids.entry(build.kind).or_default().extend(build.build_id.clone());
+            }
+            let mut statuses = HashMap::new();
+            for (kind, build_ids) in build_ids {
+                statuses.extend(self.triggers.for_kind(kind)?.statuses(&build_ids).await?);
+            }
+            pending.retain(|build| {
+                let build_id = build.build_id.as_deref().unwrap_or("-");
+                match statuses.get(build_id) {
+                    Some(BuildStatus::InProgress) | None => return true,
+                    Some(BuildStatus::Succeeded) => eprintln!("Rebuild of {} ({}) succeeded.", build.consumer, build_id),
+                    Some(status) => {
+                        eprintln!("Rebuild of {} ({}) {}.", build.consumer, build_id, status);
+                        failures.push(format!("{} ({} {})", build.consumer, build_id, status));
                     }
                 }
-                Ok(())
-            },
-            Err(err) => {
-                return Err(crate_helper::Error {
-                    msg: err.to_string()
-                })
+                false
+            });
+            if pending.is_empty() {
+                return Ok(failures);
             }
+            let now = Instant::now();
+            if now >= deadline {
+                let pending: Vec<String> = pending.iter()
+                    .map(|build| format!("{} ({})", build.consumer, build.build_id.as_deref().unwrap_or("-")))
+                    .collect();
+                return Err(Error::RebuildTimeout(pending.join(", ")));
+            }
+            eprintln!("Waiting for {} rebuild(s) to finish...", pending.len());
+            time::sleep(wait_policy.poll_interval.min(deadline - now)).await;
         }
     }
 
-    async fn rm_consumer_from_dependency(&self, pkg_key: &PackageKey, old_dep_key: PackageKey) -> Result<(), crate_helper::Error> {
+    async fn rm_consumer_from_dependency(&self, pkg_key: &PackageKey, old_dep_key: PackageKey) -> Result<(), Error> {
         let consumer_key = pkg_key.to_fq_key();
         let fq_dep_key = old_dep_key.to_fq_key();
         eprintln!("Trying to remove {} as consumer of {}.", consumer_key, fq_dep_key);
-        let dep_primary_key = old_dep_key.ddb_primary_key();
-        match self.ddb.update_item()
-            .table_name(self.pkg_metadata_table.clone())
-            // At this point we already know that the dependency's version is defined. If it's not
-            // then this function isn't being called correctly.
-            .set_key(Some(dep_primary_key))
-            .update_expression(format!("DELETE {} :d", KEY_CONSUMERS))
-            .expression_attribute_values(":d", AttributeValue::Ss(vec![consumer_key.clone()]))
-            .condition_expression(format!("attribute_exists({})", KEY_PACKAGE_NAME)).send().await {
-            Ok(_) => {
-                log::info!("{} removed as consumer of {}.", consumer_key, fq_dep_key);
-                Ok(())
+        if self.store.remove_consumer(&old_dep_key, pkg_key).await? {
+            log::info!("{} removed as consumer of {}.", consumer_key, fq_dep_key);
+        } else {
+            eprintln!("{}  not being tracked. Skipping...", fq_dep_key);
+        }
+        Ok(())
+    }
+
+    async fn rm_dependency_from_consumer(&self, pkg_key: &PackageKey, consumer_key: PackageKey) -> Result<(), Error> {
+        let dependency_key = pkg_key.to_fq_key();
+        let fq_consumer_key = consumer_key.to_fq_key();
+        if self.store.remove_dependency(&consumer_key, pkg_key).await? {
+            log::info!("{} removed as dependency of {}.", dependency_key, fq_consumer_key);
+        } else {
+            eprintln!("{} not being tracked. Skipping...", fq_consumer_key);
+        }
+        Ok(())
+    }
+
+    async fn rebuild_consumer(&self, dependency_key: &PackageKey, consumer_key: PackageKey) -> Result<Option<StartedBuild>, Error> {
+        eprintln!("Checking to see if {:?} needs to be rebuilt due to update to {:?}.", consumer_key, dependency_key);
+
+        let record = match self.store.get_record(&consumer_key).await? {
+            Some(record) => record,
+            None => return Ok(None)
+        };
+        if !record.dependencies.contains(&dependency_key.to_fq_key()) {
+            eprintln!("Dependencies does not contain {:?}", dependency_key);
+            return Ok(None);
+        }
+        let kinds = record.kinds_of(&dependency_key.to_fq_key());
+        if kinds.is_disjoint(&self.rebuild_kinds) {
+            let kinds: Vec<&str> = kinds.iter().map(|kind| kind.as_str()).collect();
+            eprintln!("{} is only a {} dependency of {}. Skipping rebuild...", dependency_key.to_fq_key(), kinds.join("/"), consumer_key.to_fq_key());
+            return Ok(None);
+        }
+        self.start_rebuild(dependency_key, &record).await
+    }
+
+    /// Fires the consumer's build target, telling it that `dependency_key` changed. Transitive
+    /// rebuilds tell it to leave its own consumers to this updater.
+    async fn start_rebuild(&self, dependency_key: &PackageKey, record: &PackageRecord) -> Result<Option<StartedBuild>, Error> {
+        let consumer_key = &record.key;
+        let build_target = match &record.build_target {
+            Some(build_target) => build_target,
+            None => {
+                eprintln!("Didn't find a build target for {:?}.", consumer_key);
+                return Ok(None);
+            }
+        };
+        let trigger = self.triggers.for_kind(build_target.kind)?;
+        match trigger.trigger(build_target, consumer_key, dependency_key, self.transitive).await? {
+            Some(build) => {
+                eprintln!("Kicked off rebuild of consumer {:?} ({}, build id {})", consumer_key, build_target, build.build_id.as_deref().unwrap_or("-"));
+                Ok(Some(StartedBuild {
+                    consumer: consumer_key.to_fq_key(),
+                    kind: build_target.kind,
+                    build_id: build.build_id,
+                }))
             },
+            None => {
+                eprintln!("Can't find {}. Skipping", build_target);
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Splits the outcomes of starting the rebuilds of `consumers` into the builds that started and
+/// the failures of those that couldn't be started. A rebuild that fails to start doesn't stop
+/// the others.
+fn started_builds(consumers: &[String], results: Vec<Result<Option<StartedBuild>, Error>>) -> (Vec<StartedBuild>, Vec<String>) {
+    let mut started = vec![];
+    let mut failures = vec![];
+    for (consumer, result) in consumers.iter().zip(results) {
+        match result {
+            Ok(build) => started.extend(build),
             Err(err) => {
-                match err {
-                    DynamoDbError::ServiceError { err, .. } => {
-                        if err.is_conditional_check_failed_exception() {
-                            eprintln!("{}  not being tracked. Skipping...", fq_dep_key);
-                            Ok(())
-                        } else {
-                            return Err(crate_helper::Error::with_msg(format!("ERROR: {}", err.to_string())))
-                        }
-                    },
-                    _ => return Err(crate_helper::Error::with_msg(format!("ERROR: {}", err.to_string())))
-                }
+                log::error!("Rebuild of {} failed to start: {}", consumer, err);
+                failures.push(format!("{} ({})", consumer, err));
             }
         }
     }
+    (started, failures)
+}
 
-    async fn rebuild_consumer(&self, dependency_key: &PackageKey, consumer_key: PackageKey) -> Result<(), crate_helper::Error> {
-        eprintln!("Checking to see if {:?} needs to be rebuilt due to update to {:?}.", consumer_key, dependency_key);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use crate::temp_project;
+    use crate::trigger::{CommandTrigger, FakeCodeBuildTrigger, RecordingTrigger};
+    use DependencyKind::Normal;
 
-        match self.ddb.get_item()
-            .table_name(String::from(self.pkg_metadata_table.clone()))
-            .set_key(Some(consumer_key.ddb_primary_key()))
-            .send().await {
-            Ok(response) => {
-                // TODO: This is not kicking off builds properly.
-                eprintln!("Found record for {:?}: {:?}", consumer_key, response);
-                if let Some(item) = response.item {
-                    eprintln!("Found item: {:?}", item);
-                    if let Some(dependencies_av) = item.get(KEY_DEPENDENCIES) {
-                        if let Ok(dependencies) = dependencies_av.as_ss() {
-                            eprintln!("Found the following dependencies for {:?}: {:?}", consumer_key, dependencies);
-                            if dependencies.contains(&dependency_key.to_fq_key()) {
-                                eprintln!("Dependencies contains {:?}", dependency_key);
-                                if let Some(cb_build_project_av) = item.get(KEY_CODE_BUILD_PROJECT_NAME) {
-                                    eprintln!("Found CodeBuildProject AttributeValue: {:?}", cb_build_project_av);
-                                    if let Ok(cb_build_project_name) = cb_build_project_av.as_s() {
-                                        return match self.codebuild.start_build().project_name(cb_build_project_name).send().await {
-                                            Ok(_) => {
-                                                eprintln!("Kicked off rebuild of consumer {:?} (CB project {}", consumer_key, cb_build_project_name);
-                                                Ok(())
-                                            },
-                                            Err(err) => {
-                                                // TODO: Clean up this mess so there isn't any duplication.
-                                                match err {
-                                                    SdkError::ServiceError { err, .. } => {
-                                                        match err.kind {
-                                                            StartBuildErrorKind::ResourceNotFoundException(_) => {
-                                                                eprintln!("Can't find a CodeBuild project named {}. Skipping", cb_build_project_name);
-                                                                return Ok(());
-                                                            },
-                                                            _ => {
-                                                                return Err(crate_helper::Error::with_msg(format!("ERROR: {}", err.to_string())))
-                                                            },
-                                                        }
-                                                    },
-                                                    _ => {
-                                                        return Err(crate_helper::Error::with_msg(format!("ERROR: {}", err.to_string())))
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                } else {
-                                    eprintln!("Didn't find CodeBuildProject AttributeValue.");
-                                }
-                            } else {
-                                eprintln!("Dependencies does not contain {:?}", dependency_key);
-                            }
-                        }
-                    }
-                }
-                Ok(())
-            },
-            Err(err) => Err(crate_helper::Error::with_msg(format!("ERROR: {}", err.to_string())))
+    /// `rust/b` and `rust/c` consume `rust/a`, and `rust/c` consumes `rust/b` too.
+    async fn chain() -> Arc<MemoryStore> {
+        let store = Arc::new(MemoryStore::new());
+        store.track("rust/a:1.0.0", "a-project", &[]).await;
+        store.track("rust/b:1.0.0", "b-project", &[("rust/a:1.0.0", Normal)]).await;
+        store.track("rust/c:1.0.0", "c-project", &[("rust/a:1.0.0", Normal), ("rust/b:1.0.0", Normal)]).await;
+        store
+    }
+
+    fn updater(store: Arc<MemoryStore>, codebuild: Arc<FakeCodeBuildTrigger>, wait_policy: WaitPolicy) -> CrateMetadataUpdater {
+        CrateMetadataUpdater::new(store, BuildTriggers::new().with(TriggerKind::CodeBuild, codebuild))
+            .with_wait_policy(Some(wait_policy))
+    }
+
+    fn wait_policy(timeout_ms: u64, abort_on_failure: bool) -> WaitPolicy {
+        WaitPolicy {
+            timeout: Duration::from_millis(timeout_ms),
+            poll_interval: Duration::from_millis(1),
+            abort_on_failure,
+        }
+    }
+
+    fn a() -> PackageKey {
+        PackageKey::from_fq_key("rust/a:1.0.0").unwrap()
+    }
+
+    #[tokio::test]
+    async fn waits_for_rebuilds_to_succeed() {
+        let codebuild = Arc::new(FakeCodeBuildTrigger::new(2));
+        let updater = updater(chain().await, codebuild.clone(), wait_policy(10_000, false));
+        updater.rebuild_consumers(&a()).await.unwrap();
+        assert_eq!(codebuild.started(), ["b-project", "c-project"]);
+    }
+
+    #[tokio::test]
+    async fn reports_failed_rebuilds() {
+        let codebuild = Arc::new(FakeCodeBuildTrigger::new(1).with_failing_project("b-project"));
+        let updater = updater(chain().await, codebuild.clone(), wait_policy(10_000, false));
+        match updater.rebuild_consumers(&a()).await {
+            Err(Error::RebuildsFailed(failures)) => assert_eq!(failures, "rust/b:1.0.0 (b-project:1 FAILED)"),
+            other => panic!("Expected a failed rebuild, got {:?}", other.map(|_| ())),
+        }
+        assert_eq!(codebuild.started(), ["b-project", "c-project"]);
+    }
+
+    #[tokio::test]
+    async fn runs_later_waves_after_a_failure_by_default() {
+        let codebuild = Arc::new(FakeCodeBuildTrigger::new(1).with_failing_project("b-project"));
+        let updater = updater(chain().await, codebuild.clone(), wait_policy(10_000, false)).with_transitive(true);
+        assert!(matches!(updater.rebuild_consumers(&a()).await, Err(Error::RebuildsFailed(_))));
+        assert_eq!(codebuild.started(), ["b-project", "c-project"]);
+    }
+
+    #[tokio::test]
+    async fn aborts_after_a_failed_wave() {
+        let codebuild = Arc::new(FakeCodeBuildTrigger::new(1).with_failing_project("b-project"));
+        let updater = updater(chain().await, codebuild.clone(), wait_policy(10_000, true)).with_transitive(true);
+        assert!(matches!(updater.rebuild_consumers(&a()).await, Err(Error::RebuildsFailed(_))));
+        assert_eq!(codebuild.started(), ["b-project"]);
+    }
+
+    #[tokio::test]
+    async fn times_out_waiting_for_rebuilds() {
+        let codebuild = Arc::new(FakeCodeBuildTrigger::new(u32::MAX));
+        let updater = updater(chain().await, codebuild, wait_policy(20, false));
+        match updater.rebuild_consumers(&a()).await {
+            Err(Error::RebuildTimeout(pending)) => assert_eq!(pending, "rust/b:1.0.0 (b-project:1), rust/c:1.0.0 (c-project:2)"),
+            other => panic!("Expected a timeout, got {:?}", other.map(|_| ())),
         }
     }
-}
\ No newline at end of file
+
+    fn recording(store: Arc<MemoryStore>) -> (CrateMetadataUpdater, Arc<RecordingTrigger>) {
+        let recorder = Arc::new(RecordingTrigger::new());
+        (CrateMetadataUpdater::new(store, BuildTriggers::all(recorder.clone())), recorder)
+    }
+
+    fn recorded(recorder: &RecordingTrigger) -> Vec<(String, String, bool)> {
+        recorder.recorded().into_iter()
+            .map(|build| (build.consumer.to_fq_key(), build.dependency.to_fq_key(), build.skip_consumer_rebuilds))
+            .collect()
+    }
+
+    fn build_details(project: &str) -> BuildDetails {
+        BuildDetails {
+            build_target: BuildTarget { kind: TriggerKind::Noop, target: project.to_string() },
+            sources: vec![],
+        }
+    }
+
+    async fn record(store: &MemoryStore, fq_key: &str) -> Option<PackageRecord> {
+        store.get_record(&PackageKey::from_fq_key(fq_key).unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn skips_consumer_rebuilds_when_asked() {
+        let store = chain().await;
+        let (updater, recorder) = recording(store.clone());
+        let updater = updater.with_skip_consumer_rebuilds(true);
+        let dir = temp_project("register-skip", &[("Cargo.toml", "[package]\nname = \"a\"\nversion = \"1.0.0\"\n")]);
+        updater.update_metadata(build_details("a-project"), &[dir.join("Cargo.toml")]).await.unwrap();
+        assert!(recorded(&recorder).is_empty());
+        assert_eq!(record(&store, "rust/a:1.0.0").await.unwrap().consumers.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rebuilds_direct_consumers_by_default() {
+        let store = chain().await;
+        store.track("rust/d:1.0.0", "d-project", &[("rust/c:1.0.0", Normal)]).await;
+        let (updater, recorder) = recording(store);
+        updater.rebuild_consumers(&a()).await.unwrap();
+        let consumers: Vec<(String, bool)> = recorded(&recorder).into_iter().map(|(consumer, _, skip)| (consumer, skip)).collect();
+        assert_eq!(consumers, [(String::from("rust/b:1.0.0"), false), (String::from("rust/c:1.0.0"), false)]);
+    }
+
+    #[tokio::test]
+    async fn rebuilds_transitive_consumers_in_waves() {
+        let store = chain().await;
+        store.track("rust/d:1.0.0", "d-project", &[("rust/c:1.0.0", Normal)]).await;
+        let (updater, recorder) = recording(store);
+        updater.with_transitive(true).rebuild_consumers(&a()).await.unwrap();
+        assert_eq!(recorded(&recorder), [
+            (String::from("rust/b:1.0.0"), String::from("rust/a:1.0.0"), true),
+            (String::from("rust/c:1.0.0"), String::from("rust/a:1.0.0"), true),
+            (String::from("rust/d:1.0.0"), String::from("rust/c:1.0.0"), true),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reports_cycles_after_rebuilding_the_rest() {
+        let store = chain().await;
+        store.track("rust/b:1.0.0", "b-project", &[("rust/a:1.0.0", Normal), ("rust/c:1.0.0", Normal)]).await;
+        store.track("rust/d:1.0.0", "d-project", &[("rust/a:1.0.0", Normal)]).await;
+        let (updater, recorder) = recording(store);
+        match updater.with_transitive(true).rebuild_consumers(&a()).await {
+            Err(Error::DependencyCycle(cycle)) => assert_eq!(cycle, ["rust/b:1.0.0", "rust/c:1.0.0", "rust/b:1.0.0"]),
+            other => panic!("Expected a cycle, got {:?}", other.map(|_| ())),
+        }
+        let consumers: Vec<String> = recorded(&recorder).into_iter().map(|(consumer, _, _)| consumer).collect();
+        assert_eq!(consumers, ["rust/d:1.0.0"]);
+    }
+
+    #[tokio::test]
+    async fn registers_relinks_and_rebuilds_consumers() {
+        let dir = temp_project("register", &[
+            ("lib/package.json", r#"{ "name": "lib", "version": "1.0.0" }"#),
+            ("other/package.json", r#"{ "name": "other", "version": "2.0.0" }"#),
+            ("app/package.json", r#"{ "name": "app", "version": "0.1.0", "dependencies": { "lib": "^1.0.0", "untracked": "^3.0.0" } }"#),
+        ]);
+        let store = Arc::new(MemoryStore::new());
+        let (updater, recorder) = recording(store.clone());
+        updater.update_metadata(build_details("lib"), &[dir.join("lib/package.json")]).await.unwrap();
+        updater.update_metadata(build_details("other"), &[dir.join("other/package.json")]).await.unwrap();
+        updater.update_metadata(build_details("app"), &[dir.join("app/package.json")]).await.unwrap();
+        assert_eq!(record(&store, "npm/app:0.1.0").await.unwrap().dependencies, BTreeSet::from([String::from("npm/lib:1.0.0")]));
+        assert_eq!(record(&store, "npm/lib:1.0.0").await.unwrap().consumers, BTreeSet::from([String::from("npm/app:0.1.0")]));
+        assert!(recorded(&recorder).is_empty());
+
+        updater.update_metadata(build_details("lib"), &[dir.join("lib/package.json")]).await.unwrap();
+        assert_eq!(recorded(&recorder), [(String::from("npm/app:0.1.0"), String::from("npm/lib:1.0.0"), false)]);
+
+        // Switching the app from lib to other moves it between their consumers.
+        std::fs::write(dir.join("app/package.json"), r#"{ "name": "app", "version": "0.1.0", "dependencies": { "other": "2.0.0" } }"#).unwrap();
+        updater.update_metadata(build_details("app"), &[dir.join("app/package.json")]).await.unwrap();
+        assert!(record(&store, "npm/lib:1.0.0").await.unwrap().consumers.is_empty());
+        assert_eq!(record(&store, "npm/other:2.0.0").await.unwrap().consumers, BTreeSet::from([String::from("npm/app:0.1.0")]));
+    }
+
+    #[tokio::test]
+    async fn forgets_a_package_and_its_edges() {
+        let store = chain().await;
+        let (updater, _) = recording(store.clone());
+        updater.forget_package(&PackageKey::from_fq_key("rust/b:1.0.0").unwrap()).await.unwrap();
+        assert!(record(&store, "rust/b:1.0.0").await.is_none());
+        assert_eq!(record(&store, "rust/a:1.0.0").await.unwrap().consumers, BTreeSet::from([String::from("rust/c:1.0.0")]));
+        assert_eq!(record(&store, "rust/c:1.0.0").await.unwrap().dependencies, BTreeSet::from([String::from("rust/a:1.0.0")]));
+    }
+
+    #[tokio::test]
+    async fn keeps_rebuilding_after_a_trigger_fails() {
+        let dir = temp_project("trigger-failure", &[]);
+        let store = Arc::new(MemoryStore::new());
+        store.track("rust/a:1.0.0", "true", &[]).await;
+        store.track("rust/b:1.0.0", "exit 3", &[("rust/a:1.0.0", Normal)]).await;
+        store.track("rust/c:1.0.0", &format!("touch {}", dir.join("c").display()), &[("rust/a:1.0.0", Normal)]).await;
+        let updater = CrateMetadataUpdater::new(store, BuildTriggers::new().with(TriggerKind::CodeBuild, Arc::new(CommandTrigger::new())));
+        match updater.rebuild_consumers(&a()).await {
+            Err(Error::RebuildsFailed(failures)) => assert!(failures.starts_with("rust/b:1.0.0 (Unable to rebuild via"), "{}", failures),
+            other => panic!("Expected a failed rebuild, got {:?}", other.map(|_| ())),
+        }
+        assert!(dir.join("c").exists());
+    }
+
+    #[tokio::test]
+    async fn links_renamed_dependencies_to_their_own_locked_version() {
+        let dir = temp_project("register-renamed", &[
+            ("Cargo.toml", "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nfoo = \"2\"\nfoo_old = { package = \"foo\", version = \"1\" }\n"),
+            ("Cargo.lock", "[[package]]\nname = \"app\"\nversion = \"0.1.0\"\ndependencies = [\n \"foo 1.4.0\",\n \"foo 2.1.0\",\n]\n\n[[package]]\nname = \"foo\"\nversion = \"1.4.0\"\n\n[[package]]\nname = \"foo\"\nversion = \"2.1.0\"\n"),
+        ]);
+        let store = Arc::new(MemoryStore::new());
+        for fq_key in ["rust/foo:1.3.0", "rust/foo:1.4.0", "rust/foo:2.0.0", "rust/foo:2.1.0"] {
+            store.track(fq_key, "foo-project", &[]).await;
+        }
+        let (updater, _) = recording(store.clone());
+        updater.update_metadata(build_details("app-project"), &[dir.join("Cargo.toml")]).await.unwrap();
+        assert_eq!(record(&store, "rust/app:0.1.0").await.unwrap().dependencies,
+                   BTreeSet::from([String::from("rust/foo:1.4.0"), String::from("rust/foo:2.1.0")]));
+        assert!(record(&store, "rust/foo:1.3.0").await.unwrap().consumers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn links_git_dependencies_to_their_repository_and_ref() {
+        let dir = temp_project("register-git", &[
+            ("Cargo.toml", "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nlib = { git = \"https://github.com/Org/lib.git\", branch = \"refs/heads/release\" }\nother = { git = \"git@github.com:Org/other.git\" }\n"),
+        ]);
+        let store = Arc::new(MemoryStore::new());
+        store.track("git/github.com/Org/lib:release", "lib-project", &[]).await;
+        store.track("git/github.com/Org/other:HEAD", "other-project", &[]).await;
+        let (updater, _) = recording(store.clone());
+        updater.update_metadata(build_details("app-project"), &[dir.join("Cargo.toml")]).await.unwrap();
+        assert_eq!(record(&store, "rust/app:0.1.0").await.unwrap().dependencies,
+                   BTreeSet::from([String::from("git/github.com/Org/lib:release"), String::from("git/github.com/Org/other:HEAD")]));
+    }
+
+    #[tokio::test]
+    async fn skips_dependencies_from_registries_that_are_not_tracked() {
+        let dir = temp_project("register-registries", &[
+            ("Cargo.toml", "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\npublic = \"1\"\ninternal = { version = \"1\", registry = \"corp\" }\nvendored = { git = \"https://github.com/Org/vendored\" }\n"),
+        ]);
+        let store = Arc::new(MemoryStore::new());
+        store.track("rust/public:1.0.0", "public-project", &[]).await;
+        store.track("rust/internal:1.0.0", "internal-project", &[]).await;
+        store.track("git/github.com/Org/vendored:HEAD", "vendored-project", &[]).await;
+        let (updater, _) = recording(store.clone());
+        let updater = updater.with_tracked_registries(HashSet::from([String::from("corp")]));
+        updater.update_metadata(build_details("app-project"), &[dir.join("Cargo.toml")]).await.unwrap();
+        assert_eq!(record(&store, "rust/app:0.1.0").await.unwrap().dependencies,
+                   BTreeSet::from([String::from("git/github.com/Org/vendored:HEAD"), String::from("rust/internal:1.0.0")]));
+        assert!(record(&store, "rust/public:1.0.0").await.unwrap().consumers.is_empty());
+    }
+}
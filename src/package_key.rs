@@ -0,0 +1,148 @@
+use once_cell::sync::OnceCell;
+use regex::Regex;
+use crate::error::Error;
+use crate::manifest::ManifestReader;
+
+/// The build system of packages read from a Cargo.toml.
+pub const BUILD_SYSTEM: &str = "rust";
+/// The build system of packages read from a package.json.
+pub const NPM_BUILD_SYSTEM: &str = "npm";
+/// The build system of packages read from a pom.xml. Their names are "groupId:artifactId".
+pub const MAVEN_BUILD_SYSTEM: &str = "maven";
+/// The build system of packages read from a pyproject.toml. Their names are normalized as in PEP 503.
+pub const PYPI_BUILD_SYSTEM: &str = "pypi";
+/// The build system of modules read from a go.mod. Their names are module paths.
+pub const GO_BUILD_SYSTEM: &str = "go";
+/// The build system of packages read from a .csproj. Their names are lowercase package ids.
+pub const NUGET_BUILD_SYSTEM: &str = "nuget";
+/// The build system of keys that identify a git repository and ref rather than a package.
+pub const GIT_BUILD_SYSTEM: &str = "git";
+/// The ref of git keys for dependencies that follow the repository's default branch.
+pub const GIT_DEFAULT_REF: &str = "HEAD";
+
+const KEY_BUILD_SYSTEM_AND_NAME_DELIMITER: &str = "/";
+const KEY_NAME_AND_VERSION_DELIMITER: &str = ":";
+
+// Names can contain '/' and ':' (git keys use repository URLs), but build systems and versions can't.
+const PKG_KEY_REGEX: &str = "^([^/]+)/(.+):([^:]+)$";
+
+fn package_key_pattern() -> &'static Regex {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    INSTANCE.get_or_init(|| Regex::new(PKG_KEY_REGEX).unwrap())
+}
+
+/// Identifies one version of a package, written as "build_system/name:version".
+#[derive(Clone, Debug)]
+pub struct PackageKey {
+    pub build_system: String,
+    pub name: String,
+    pub version: String,
+}
+
+impl PackageKey {
+    /// Parses a fully qualified key such as "rust/my-crate:0.1.0".
+    pub fn from_fq_key(fq_key: &str) -> Result<PackageKey, Error> {
+        match package_key_pattern().captures(fq_key) {
+            Some(match_elements) => {
+                let build_system = String::from(match_elements.get(1).map(|m| m.as_str()).expect("Expected build system to be present"));
+                let name = String::from(match_elements.get(2).map(|m| m.as_str()).expect("Expected package name to be present."));
+                let version = String::from(match_elements.get(3).map(|m| m.as_str()).expect("Expected version to be present."));
+                Ok(PackageKey {
+                    build_system,
+                    name,
+                    version,
+                })
+            },
+            None => Err(Error::InvalidPackageKey(fq_key.to_string()))
+        }
+    }
+
+    /// Formats the key as "build_system/name:version".
+    pub fn to_fq_key(&self) -> String {
+        format!("{}{}{}{}{}", self.build_system, KEY_BUILD_SYSTEM_AND_NAME_DELIMITER, self.name, KEY_NAME_AND_VERSION_DELIMITER, self.version)
+    }
+
+    /// The key of a git repository at `reference` (a branch, tag or revision), or at its default
+    /// branch if there's no reference.
+    pub fn for_git(url: &str, reference: Option<&str>) -> PackageKey {
+        PackageKey {
+            build_system: String::from(GIT_BUILD_SYSTEM),
+            name: normalize_git_url(url),
+            version: match reference {
+                Some(reference) => normalize_git_ref(reference),
+                None => String::from(GIT_DEFAULT_REF),
+            },
+        }
+    }
+
+    /// The build system and name portion of the key (e.g. "rust/my-crate"), shared by every
+    /// version of a package.
+    pub fn qualified_name(&self) -> String {
+        format!("{}{}{}", self.build_system, KEY_BUILD_SYSTEM_AND_NAME_DELIMITER, self.name)
+    }
+}
+
+impl<M: ManifestReader + ?Sized> From<&M> for PackageKey {
+    fn from(manifest: &M) -> Self {
+        PackageKey {
+            build_system: String::from(manifest.build_system()),
+            name: manifest.name(),
+            version: manifest.version(),
+        }
+    }
+}
+
+/// Reduces the different spellings of a repository URL to one: no scheme, user or trailing
+/// ".git", and a lowercase host. "git@github.com:Org/repo.git" and
+/// "https://github.com/Org/repo" both become "github.com/Org/repo".
+pub fn normalize_git_url(url: &str) -> String {
+    let url = url.trim().trim_start_matches("git+");
+    let without_scheme = match url.split_once("://") {
+        Some((_, rest)) => rest.to_string(),
+        // scp-like syntax: user@host:path
+        None => url.replacen(':', "/", 1),
+    };
+    let without_user = match without_scheme.split_once('@') {
+        Some((user, rest)) if !user.contains('/') => rest,
+        _ => &without_scheme,
+    };
+    let path = without_user.trim_end_matches('/').trim_end_matches(".git");
+    match path.split_once('/') {
+        Some((host, rest)) => format!("{}/{}", host.to_lowercase(), rest),
+        None => path.to_lowercase(),
+    }
+}
+
+/// Strips the "refs/heads/" or "refs/tags/" prefix from a fully qualified git ref.
+pub fn normalize_git_ref(reference: &str) -> String {
+    reference.trim_start_matches("refs/heads/").trim_start_matches("refs/tags/").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_fully_qualified_git_refs() {
+        assert_eq!(normalize_git_ref("refs/heads/main"), "main");
+        assert_eq!(normalize_git_ref("refs/tags/v1.2.0"), "v1.2.0");
+        assert_eq!(normalize_git_ref("feature/refs/heads"), "feature/refs/heads");
+        assert_eq!(normalize_git_ref("3f2a9c1"), "3f2a9c1");
+    }
+
+    #[test]
+    fn spells_repository_urls_one_way() {
+        for url in ["git@github.com:Org/repo.git", "https://github.com/Org/repo", "git+ssh://git@GitHub.com/Org/repo.git", "https://github.com/Org/repo/"] {
+            assert_eq!(normalize_git_url(url), "github.com/Org/repo", "{}", url);
+        }
+    }
+
+    #[test]
+    fn keys_git_dependencies_by_repository_and_ref() {
+        let key = PackageKey::for_git("git@github.com:Org/repo.git", Some("refs/tags/v1"));
+        assert_eq!(key.to_fq_key(), "git/github.com/Org/repo:v1");
+        assert_eq!(PackageKey::for_git("https://github.com/Org/repo", None).to_fq_key(), "git/github.com/Org/repo:HEAD");
+        let parsed = PackageKey::from_fq_key("git/github.com/Org/repo:v1").unwrap();
+        assert_eq!((parsed.build_system.as_str(), parsed.name.as_str(), parsed.version.as_str()), ("git", "github.com/Org/repo", "v1"));
+    }
+}
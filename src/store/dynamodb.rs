@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use async_trait::async_trait;
+use aws_config::Config;
+use aws_sdk_dynamodb::{Client as DynamoDbClient, SdkError as DynamoDbError};
+use aws_sdk_dynamodb::error::{DeleteItemError, GetItemError, QueryError, ScanError, UpdateItemError};
+use aws_sdk_dynamodb::model::{AttributeAction, AttributeValue, AttributeValueUpdate, ReturnValue};
+use crate::manifest::DependencyKind;
+use crate::error::Error;
+use crate::package_key::PackageKey;
+use crate::store::{MetadataStore, PackageRecord, PackageUpdate};
+use crate::trigger::{BuildTarget, TriggerKind};
+
+const KEY_CODE_BUILD_PROJECT_NAME: &str = "code_build_project_name";
+const KEY_BUILD_TRIGGER: &str = "build_trigger";
+const KEY_BUILD_TARGET: &str = "build_target";
+const KEY_PACKAGE_NAME: &str = "package_name";
+const KEY_VERSION: &str = "version";
+const KEY_CONSUMERS: &str = "consumers";
+const KEY_DEPENDENCIES: &str = "dependencies";
+const KEY_DEPENDENCY_KINDS: &str = "dependency_kinds";
+
+const CODE_RESOURCE_NOT_FOUND: &str = "ResourceNotFoundException";
+const THROTTLING_CODES: [&str; 3] = ["ProvisionedThroughputExceededException", "RequestLimitExceeded", "ThrottlingException"];
+
+/// Access to the error code of DynamoDB operation errors, which don't share a trait for it.
+trait ErrorCode {
+    fn error_code(&self) -> Option<&str>;
+}
+
+macro_rules! impl_error_code {
+    ($($error:ty),*) => {
+        $(impl ErrorCode for $error {
+            fn error_code(&self) -> Option<&str> {
+                self.code()
+            }
+        })*
+    };
+}
+
+impl_error_code!(UpdateItemError, GetItemError, DeleteItemError, QueryError, ScanError);
+
+/// A [`MetadataStore`] backed by a DynamoDB table keyed on `package_name` (build system and name)
+/// and `version`.
+pub struct DynamoDbStore {
+    ddb: DynamoDbClient,
+    pkg_metadata_table: String,
+}
+
+impl DynamoDbStore {
+    pub fn new(client_config: &Config, pkg_metadata_table: String) -> DynamoDbStore {
+        DynamoDbStore {
+            ddb: DynamoDbClient::new(client_config),
+            pkg_metadata_table,
+        }
+    }
+
+    fn store_error<E>(&self, err: DynamoDbError<E>) -> Error
+        where E: ErrorCode + std::error::Error + Send + Sync + 'static {
+        match err {
+            DynamoDbError::ServiceError { err, .. } => {
+                let code = err.error_code().unwrap_or_default();
+                if code == CODE_RESOURCE_NOT_FOUND {
+                    Error::TableNotFound(self.pkg_metadata_table.clone())
+                } else if THROTTLING_CODES.contains(&code) {
+                    Error::Throttled(Box::new(err))
+                } else {
+                    Error::Store(Box::new(err))
+                }
+            },
+            err => Error::Store(err.to_string().into()),
+        }
+    }
+
+    /// Adds or deletes `value` in the string set `attribute` of an existing record. Returns
+    /// `false` if the record doesn't exist.
+    async fn update_string_set(&self, pkg_key: &PackageKey, action: &str, attribute: &str, value: String) -> Result<bool, Error> {
+        match self.ddb.update_item()
+            .table_name(self.pkg_metadata_table.clone())
+            .set_key(Some(ddb_primary_key(pkg_key)))
+            .update_expression(format!("{} {} :d", action, attribute))
+            .expression_attribute_values(":d", AttributeValue::Ss(vec![value]))
+            .condition_expression(format!("attribute_exists({})", KEY_PACKAGE_NAME)).send().await {
+            Ok(_) => Ok(true),
+            Err(err) => {
+                match err {
+                    DynamoDbError::ServiceError { err, .. } if err.is_conditional_check_failed_exception() => Ok(false),
+                    err => Err(self.store_error(err))
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MetadataStore for DynamoDbStore {
+    async fn add_consumer(&self, dependency: &PackageKey, consumer: &PackageKey) -> Result<bool, Error> {
+        self.update_string_set(dependency, "ADD", KEY_CONSUMERS, consumer.to_fq_key()).await
+    }
+
+    async fn remove_consumer(&self, dependency: &PackageKey, consumer: &PackageKey) -> Result<bool, Error> {
+        self.update_string_set(dependency, "DELETE", KEY_CONSUMERS, consumer.to_fq_key()).await
+    }
+
+    async fn remove_dependency(&self, consumer: &PackageKey, dependency: &PackageKey) -> Result<bool, Error> {
+        self.update_string_set(consumer, "DELETE", KEY_DEPENDENCIES, dependency.to_fq_key()).await
+    }
+
+    async fn upsert_package(&self, package: &PackageKey, update: PackageUpdate) -> Result<Option<PackageRecord>, Error> {
+        let (dep_attribute_update, kinds_attribute_update) = if update.dependencies.is_empty() {
+            // If there are no tracked dependencies, delete the dependencies value.
+            (AttributeValueUpdate::builder().action(AttributeAction::Delete).build(),
+             AttributeValueUpdate::builder().action(AttributeAction::Delete).build())
+        } else {
+            // Kinds are kept in a map from each dependency to the set of kinds it was declared as.
+            let kinds = update.dependencies.iter()
+                .map(|(dependency, kinds)| (dependency.clone(), AttributeValue::Ss(kinds.iter().map(|kind| kind.to_string()).collect())))
+                .collect();
+            (AttributeValueUpdate::builder().value(AttributeValue::Ss(update.dependencies.into_keys().collect())).build(),
+             AttributeValueUpdate::builder().value(AttributeValue::M(kinds)).build())
+        };
+
+        // CodeBuild targets keep using code_build_project_name so that records stay readable by
+        // earlier versions of this tool. Every other trigger is stored as a kind and a target.
+        let put = |value: &str| AttributeValueUpdate::builder().value(AttributeValue::S(String::from(value))).build();
+        let delete = || AttributeValueUpdate::builder().action(AttributeAction::Delete).build();
+        let (project_name_update, trigger_update, target_update) = match update.build_target.kind {
+            TriggerKind::CodeBuild => (put(&update.build_target.target), delete(), delete()),
+            kind => (delete(), put(kind.as_str()), put(&update.build_target.target)),
+        };
+
+        match self.ddb.update_item()
+            .table_name(self.pkg_metadata_table.clone())
+            .set_key(Some(ddb_primary_key(package)))
+            .attribute_updates(KEY_CODE_BUILD_PROJECT_NAME, project_name_update)
+            .attribute_updates(KEY_BUILD_TRIGGER, trigger_update)
+            .attribute_updates(KEY_BUILD_TARGET, target_update)
+            .attribute_updates(KEY_DEPENDENCIES, dep_attribute_update)
+            .attribute_updates(KEY_DEPENDENCY_KINDS, kinds_attribute_update)
+            .return_values(ReturnValue::AllOld)
+            .send().await {
+            Ok(response) => Ok(response.attributes.map(|item| to_record(package, &item))),
+            Err(err) => Err(self.store_error(err))
+        }
+    }
+
+    async fn get_record(&self, package: &PackageKey) -> Result<Option<PackageRecord>, Error> {
+        match self.ddb.get_item()
+            .table_name(self.pkg_metadata_table.clone())
+            .set_key(Some(ddb_primary_key(package)))
+            .send().await {
+            Ok(response) => Ok(response.item.map(|item| to_record(package, &item))),
+            Err(err) => Err(self.store_error(err))
+        }
+    }
+
+    async fn delete_record(&self, package: &PackageKey) -> Result<Option<PackageRecord>, Error> {
+        match self.ddb.delete_item()
+            .table_name(self.pkg_metadata_table.clone())
+            .set_key(Some(ddb_primary_key(package)))
+            .return_values(ReturnValue::AllOld)
+            .send().await {
+            Ok(response) => Ok(response.attributes.map(|item| to_record(package, &item))),
+            Err(err) => Err(self.store_error(err))
+        }
+    }
+
+    async fn list_versions(&self, build_system: &str, name: &str) -> Result<Vec<String>, Error> {
+        let mut versions = vec![];
+        let mut exclusive_start_key = None;
+        loop {
+            let response = match self.ddb.query()
+                .table_name(self.pkg_metadata_table.clone())
+                .key_condition_expression(format!("{} = :n", KEY_PACKAGE_NAME))
+                .expression_attribute_values(":n", AttributeValue::S(format!("{}/{}", build_system, name)))
+                .set_exclusive_start_key(exclusive_start_key)
+                .send().await {
+                Ok(response) => response,
+                Err(err) => return Err(self.store_error(err))
+            };
+            for item in response.items.unwrap_or_default() {
+                if let Some(Ok(version)) = item.get(KEY_VERSION).map(|av| av.as_s()) {
+                    versions.push(version.clone());
+                }
+            }
+            match response.last_evaluated_key {
+                Some(key) => exclusive_start_key = Some(key),
+                None => return Ok(versions),
+            }
+        }
+    }
+
+    async fn list_records(&self) -> Result<Vec<PackageRecord>, Error> {
+        let mut records = vec![];
+        let mut exclusive_start_key = None;
+        loop {
+            let response = match self.ddb.scan()
+                .table_name(self.pkg_metadata_table.clone())
+                .set_exclusive_start_key(exclusive_start_key)
+                .send().await {
+                Ok(response) => response,
+                Err(err) => return Err(self.store_error(err))
+            };
+            for item in response.items.unwrap_or_default() {
+                let string = |attribute: &str| item.get(attribute).and_then(|av| av.as_s().ok());
+                if let (Some(package_name), Some(version)) = (string(KEY_PACKAGE_NAME), string(KEY_VERSION)) {
+                    let pkg_key = PackageKey::from_fq_key(&format!("{}:{}", package_name, version))?;
+                    records.push(to_record(&pkg_key, &item));
+                }
+            }
+            match response.last_evaluated_key {
+                Some(key) => exclusive_start_key = Some(key),
+                None => return Ok(records),
+            }
+        }
+    }
+}
+
+fn ddb_primary_key(pkg_key: &PackageKey) -> HashMap<String, AttributeValue> {
+    let mut key: HashMap<String, AttributeValue> = HashMap::new();
+    key.insert(String::from(KEY_PACKAGE_NAME), AttributeValue::S(pkg_key.qualified_name()));
+    key.insert(String::from(KEY_VERSION), AttributeValue::S(pkg_key.version.clone()));
+    key
+}
+
+fn to_record(pkg_key: &PackageKey, item: &HashMap<String, AttributeValue>) -> PackageRecord {
+    let string_set = |attribute: &str| -> BTreeSet<String> {
+        match item.get(attribute).map(|av| av.as_ss()) {
+            Some(Ok(values)) => values.iter().cloned().collect(),
+            _ => BTreeSet::new(),
+        }
+    };
+    let string = |attribute: &str| -> Option<String> {
+        item.get(attribute).and_then(|av| av.as_s().ok()).cloned()
+    };
+    let build_target = match (string(KEY_BUILD_TRIGGER), string(KEY_BUILD_TARGET), string(KEY_CODE_BUILD_PROJECT_NAME)) {
+        (Some(kind), Some(target), _) => match kind.parse() {
+            Ok(kind) => Some(BuildTarget { kind, target }),
+            Err(err) => {
+                eprintln!("Ignoring build target of {}: {}", pkg_key.to_fq_key(), err);
+                None
+            }
+        },
+        (_, _, Some(project_name)) => Some(BuildTarget { kind: TriggerKind::CodeBuild, target: project_name }),
+        _ => None,
+    };
+    let dependencies = string_set(KEY_DEPENDENCIES);
+    // remove_dependency only updates the dependencies set, so ignore kinds of removed ones.
+    let dependency_kinds = match item.get(KEY_DEPENDENCY_KINDS).map(|av| av.as_m()) {
+        Some(Ok(kinds)) => kinds.iter()
+            .filter(|(dependency, _)| dependencies.contains(*dependency))
+            .map(|(dependency, kinds)| (dependency.clone(), match kinds.as_ss() {
+                Ok(kinds) => kinds.iter().filter_map(|kind| kind.parse::<DependencyKind>().ok()).collect(),
+                Err(_) => BTreeSet::new(),
+            }))
+            .collect(),
+        _ => BTreeMap::new(),
+    };
+    PackageRecord {
+        key: pkg_key.clone(),
+        build_target,
+        consumers: string_set(KEY_CONSUMERS),
+        dependencies,
+        dependency_kinds,
+    }
+}
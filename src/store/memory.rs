@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use async_trait::async_trait;
+use crate::manifest::DependencyKind;
+use crate::error::Error;
+use crate::package_key::PackageKey;
+use crate::store::{MetadataStore, PackageRecord, PackageUpdate};
+use crate::trigger::BuildTarget;
+
+#[derive(Clone)]
+struct Item {
+    build_target: Option<BuildTarget>,
+    consumers: BTreeSet<String>,
+    dependencies: BTreeMap<String, BTreeSet<DependencyKind>>,
+}
+
+/// A [`MetadataStore`] that keeps records in memory for the lifetime of the process.
+///
+/// Writes behave like their DynamoDB counterparts: edge updates only apply to records that
+/// already exist, empty sets are indistinguishable from missing ones, upserts create records
+/// that don't exist yet, and upserts and deletes hand back the whole record as it was before the
+/// write.
+#[derive(Default)]
+pub struct MemoryStore {
+    // Keyed like the DynamoDB table: qualified name, then version.
+    items: Mutex<BTreeMap<(String, String), Item>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// Tracks `fq_key` as rebuilt by the CodeBuild project `project`, and as a consumer of each
+    /// of `dependencies` with the kind it was declared as. Dependencies must be tracked first.
+    #[cfg(test)]
+    pub(crate) async fn track(&self, fq_key: &str, project: &str, dependencies: &[(&str, DependencyKind)]) {
+        let pkg_key = PackageKey::from_fq_key(fq_key).unwrap();
+        let update = PackageUpdate {
+            build_target: BuildTarget { kind: crate::trigger::TriggerKind::CodeBuild, target: project.to_string() },
+            dependencies: dependencies.iter()
+                .map(|(dependency, kind)| (dependency.to_string(), BTreeSet::from([*kind])))
+                .collect(),
+        };
+        self.upsert_package(&pkg_key, update).await.unwrap();
+        for (dependency, _) in dependencies {
+            self.add_consumer(&PackageKey::from_fq_key(dependency).unwrap(), &pkg_key).await.unwrap();
+        }
+    }
+
+    fn with_item<T>(&self, pkg_key: &PackageKey, update: impl FnOnce(&mut Item) -> T) -> Option<T> {
+        let mut items = self.items.lock().expect("Memory store lock was poisoned");
+        items.get_mut(&primary_key(pkg_key)).map(update)
+    }
+}
+
+#[async_trait]
+impl MetadataStore for MemoryStore {
+    async fn add_consumer(&self, dependency: &PackageKey, consumer: &PackageKey) -> Result<bool, Error> {
+        Ok(self.with_item(dependency, |item| item.consumers.insert(consumer.to_fq_key())).is_some())
+    }
+
+    async fn remove_consumer(&self, dependency: &PackageKey, consumer: &PackageKey) -> Result<bool, Error> {
+        Ok(self.with_item(dependency, |item| item.consumers.remove(&consumer.to_fq_key())).is_some())
+    }
+
+    async fn remove_dependency(&self, consumer: &PackageKey, dependency: &PackageKey) -> Result<bool, Error> {
+        Ok(self.with_item(consumer, |item| item.dependencies.remove(&dependency.to_fq_key())).is_some())
+    }
+
+    async fn upsert_package(&self, package: &PackageKey, update: PackageUpdate) -> Result<Option<PackageRecord>, Error> {
+        let mut items = self.items.lock().expect("Memory store lock was poisoned");
+        let old_item = items.get(&primary_key(package)).cloned();
+        let item = Item {
+            build_target: Some(update.build_target),
+            consumers: old_item.as_ref().map(|item| item.consumers.clone()).unwrap_or_default(),
+            dependencies: update.dependencies,
+        };
+        items.insert(primary_key(package), item);
+        Ok(old_item.map(|item| to_record(package, item)))
+    }
+
+    async fn get_record(&self, package: &PackageKey) -> Result<Option<PackageRecord>, Error> {
+        Ok(self.with_item(package, |item| to_record(package, item.clone())))
+    }
+
+    async fn delete_record(&self, package: &PackageKey) -> Result<Option<PackageRecord>, Error> {
+        let mut items = self.items.lock().expect("Memory store lock was poisoned");
+        Ok(items.remove(&primary_key(package)).map(|item| to_record(package, item)))
+    }
+
+    async fn list_versions(&self, build_system: &str, name: &str) -> Result<Vec<String>, Error> {
+        let qualified_name = format!("{}/{}", build_system, name);
+        let items = self.items.lock().expect("Memory store lock was poisoned");
+        Ok(items.keys()
+            .filter(|(item_name, _)| *item_name == qualified_name)
+            .map(|(_, version)| version.clone())
+            .collect())
+    }
+
+    async fn list_records(&self) -> Result<Vec<PackageRecord>, Error> {
+        let items = self.items.lock().expect("Memory store lock was poisoned");
+        let mut records = vec![];
+        for ((qualified_name, version), item) in items.iter() {
+            let pkg_key = PackageKey::from_fq_key(&format!("{}:{}", qualified_name, version))?;
+            records.push(to_record(&pkg_key, item.clone()));
+        }
+        Ok(records)
+    }
+}
+
+fn primary_key(pkg_key: &PackageKey) -> (String, String) {
+    (pkg_key.qualified_name(), pkg_key.version.clone())
+}
+
+fn to_record(pkg_key: &PackageKey, item: Item) -> PackageRecord {
+    PackageRecord {
+        key: pkg_key.clone(),
+        build_target: item.build_target,
+        consumers: item.consumers,
+        dependencies: item.dependencies.keys().cloned().collect(),
+        dependency_kinds: item.dependencies,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::contract;
+
+    #[tokio::test]
+    async fn edge_writes_need_a_tracked_record() {
+        contract::edge_writes_need_a_tracked_record(&MemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn upserts_keep_consumers() {
+        contract::upserts_keep_consumers(&MemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn deletes_return_the_record_once() {
+        contract::deletes_return_the_record_once(&MemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn lists_records_in_key_order() {
+        contract::lists_records_in_key_order(&MemoryStore::new()).await;
+    }
+}
@@ -0,0 +1,193 @@
+mod dynamodb;
+mod memory;
+mod sqlite;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use async_trait::async_trait;
+use crate::manifest::DependencyKind;
+use crate::error::Error;
+use crate::package_key::PackageKey;
+use crate::trigger::BuildTarget;
+
+pub use dynamodb::DynamoDbStore;
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+/// The stored state of a tracked package.
+#[derive(Clone, Debug)]
+pub struct PackageRecord {
+    pub key: PackageKey,
+    pub build_target: Option<BuildTarget>,
+    pub consumers: BTreeSet<String>,
+    pub dependencies: BTreeSet<String>,
+    /// How each of `dependencies` was declared. Dependencies recorded before kinds were tracked
+    /// have no entry.
+    pub dependency_kinds: BTreeMap<String, BTreeSet<DependencyKind>>,
+}
+
+impl PackageRecord {
+    /// How `dependency` was declared, treating dependencies recorded without kinds as normal.
+    pub fn kinds_of(&self, dependency: &str) -> BTreeSet<DependencyKind> {
+        match self.dependency_kinds.get(dependency) {
+            Some(kinds) if !kinds.is_empty() => kinds.clone(),
+            _ => BTreeSet::from([DependencyKind::Normal]),
+        }
+    }
+}
+
+impl fmt::Display for PackageRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "package: {}", self.key.to_fq_key())?;
+        match &self.build_target {
+            Some(build_target) => {
+                writeln!(f, "build_trigger: {}", build_target.kind)?;
+                writeln!(f, "build_target: {}", build_target.target)?;
+            },
+            None => writeln!(f, "build_target: -")?,
+        }
+        writeln!(f, "dependencies:")?;
+        for dependency in &self.dependencies {
+            let kinds: Vec<&str> = self.kinds_of(dependency).iter().map(|kind| kind.as_str()).collect();
+            writeln!(f, "  {} ({})", dependency, kinds.join(", "))?;
+        }
+        writeln!(f, "consumers:")?;
+        for consumer in &self.consumers {
+            writeln!(f, "  {}", consumer)?;
+        }
+        Ok(())
+    }
+}
+
+/// The fields of a package's record that are owned by the package itself. Its consumers are
+/// owned by other packages and are left untouched when these are written.
+pub struct PackageUpdate {
+    pub build_target: BuildTarget,
+    /// Tracked dependencies, with how each was declared.
+    pub dependencies: BTreeMap<String, BTreeSet<DependencyKind>>,
+}
+
+/// Persists package records and the consumer/dependency edges between them.
+///
+/// Every write that targets an existing record (adding or removing an edge) only applies if that
+/// record is being tracked; untracked packages are reported back rather than created.
+#[async_trait]
+pub trait MetadataStore: Send + Sync {
+    /// Adds `consumer` to the consumers of `dependency`. Returns `false` if `dependency` isn't
+    /// being tracked.
+    async fn add_consumer(&self, dependency: &PackageKey, consumer: &PackageKey) -> Result<bool, Error>;
+
+    /// Removes `consumer` from the consumers of `dependency`. Returns `false` if `dependency`
+    /// isn't being tracked.
+    async fn remove_consumer(&self, dependency: &PackageKey, consumer: &PackageKey) -> Result<bool, Error>;
+
+    /// Removes `dependency` from the dependencies of `consumer`. Returns `false` if `consumer`
+    /// isn't being tracked.
+    async fn remove_dependency(&self, consumer: &PackageKey, dependency: &PackageKey) -> Result<bool, Error>;
+
+    /// Creates the package's record, or replaces the fields in `update` if it already exists.
+    /// Returns the record as it was before the write.
+    async fn upsert_package(&self, package: &PackageKey, update: PackageUpdate) -> Result<Option<PackageRecord>, Error>;
+
+    /// Fetches a package's record.
+    async fn get_record(&self, package: &PackageKey) -> Result<Option<PackageRecord>, Error>;
+
+    /// Deletes a package's record, returning it as it was before the delete.
+    async fn delete_record(&self, package: &PackageKey) -> Result<Option<PackageRecord>, Error>;
+
+    /// Lists every tracked version of the package with the given build system and name.
+    async fn list_versions(&self, build_system: &str, name: &str) -> Result<Vec<String>, Error>;
+
+    /// Fetches the record of every tracked package. This reads the whole store, so it's meant for
+    /// analysis of the package graph rather than for registering packages.
+    async fn list_records(&self) -> Result<Vec<PackageRecord>, Error>;
+}
+
+/// Checks that every [`MetadataStore`] is expected to pass, shared by the tests of each store.
+#[cfg(test)]
+pub(crate) mod contract {
+    use std::collections::{BTreeMap, BTreeSet};
+    use crate::manifest::DependencyKind;
+    use crate::package_key::PackageKey;
+    use crate::trigger::{BuildTarget, TriggerKind};
+    use super::{MetadataStore, PackageUpdate};
+
+    fn key(fq_key: &str) -> PackageKey {
+        PackageKey::from_fq_key(fq_key).unwrap()
+    }
+
+    fn update(project: &str, dependencies: &[(&str, DependencyKind)]) -> PackageUpdate {
+        PackageUpdate {
+            build_target: BuildTarget { kind: TriggerKind::CodeBuild, target: project.to_string() },
+            dependencies: dependencies.iter()
+                .map(|(dependency, kind)| (dependency.to_string(), BTreeSet::from([*kind])))
+                .collect(),
+        }
+    }
+
+    /// Edge writes only apply to tracked records, and repeating them changes nothing.
+    pub(crate) async fn edge_writes_need_a_tracked_record(store: &dyn MetadataStore) {
+        let (lib, app) = (key("rust/lib:1.0.0"), key("rust/app:0.1.0"));
+        assert!(!store.add_consumer(&lib, &app).await.unwrap());
+        assert!(!store.remove_consumer(&lib, &app).await.unwrap());
+        assert!(!store.remove_dependency(&app, &lib).await.unwrap());
+        assert!(store.get_record(&lib).await.unwrap().is_none());
+
+        store.upsert_package(&lib, update("lib-project", &[])).await.unwrap();
+        assert!(store.add_consumer(&lib, &app).await.unwrap());
+        assert!(store.add_consumer(&lib, &app).await.unwrap());
+        assert_eq!(store.get_record(&lib).await.unwrap().unwrap().consumers, BTreeSet::from([app.to_fq_key()]));
+        assert!(store.remove_consumer(&lib, &app).await.unwrap());
+        assert!(store.remove_consumer(&lib, &app).await.unwrap());
+        assert!(store.get_record(&lib).await.unwrap().unwrap().consumers.is_empty());
+    }
+
+    /// Upserts replace the package's own fields, keep its consumers and return the old record.
+    pub(crate) async fn upserts_keep_consumers(store: &dyn MetadataStore) {
+        let (lib, app) = (key("rust/lib:1.0.0"), key("rust/app:0.1.0"));
+        store.upsert_package(&lib, update("lib-project", &[])).await.unwrap();
+        assert!(store.upsert_package(&app, update("app-project", &[("rust/lib:1.0.0", DependencyKind::Dev)])).await.unwrap().is_none());
+        store.add_consumer(&lib, &app).await.unwrap();
+
+        let old = store.upsert_package(&lib, update("new-lib-project", &[])).await.unwrap().unwrap();
+        assert_eq!(old.build_target.unwrap().target, "lib-project");
+        assert_eq!(old.consumers, BTreeSet::from([app.to_fq_key()]));
+        let record = store.get_record(&lib).await.unwrap().unwrap();
+        assert_eq!(record.build_target, Some(BuildTarget { kind: TriggerKind::CodeBuild, target: String::from("new-lib-project") }));
+        assert_eq!(record.consumers, BTreeSet::from([app.to_fq_key()]));
+
+        let record = store.get_record(&app).await.unwrap().unwrap();
+        assert_eq!(record.dependencies, BTreeSet::from([lib.to_fq_key()]));
+        assert_eq!(record.kinds_of(&lib.to_fq_key()), BTreeSet::from([DependencyKind::Dev]));
+        assert!(store.remove_dependency(&app, &lib).await.unwrap());
+        let record = store.get_record(&app).await.unwrap().unwrap();
+        assert!(record.dependencies.is_empty());
+        assert_eq!(record.dependency_kinds, BTreeMap::new());
+    }
+
+    /// Deletes return the record once and leave nothing behind for edge writes to land on.
+    pub(crate) async fn deletes_return_the_record_once(store: &dyn MetadataStore) {
+        let (lib, app) = (key("rust/lib:1.0.0"), key("rust/app:0.1.0"));
+        store.upsert_package(&lib, update("lib-project", &[])).await.unwrap();
+        store.add_consumer(&lib, &app).await.unwrap();
+
+        let deleted = store.delete_record(&lib).await.unwrap().unwrap();
+        assert_eq!(deleted.key.to_fq_key(), lib.to_fq_key());
+        assert_eq!(deleted.consumers, BTreeSet::from([app.to_fq_key()]));
+        assert!(store.delete_record(&lib).await.unwrap().is_none());
+        assert!(store.get_record(&lib).await.unwrap().is_none());
+        assert!(!store.add_consumer(&lib, &app).await.unwrap());
+        assert!(store.list_versions("rust", "lib").await.unwrap().is_empty());
+    }
+
+    /// Listings cover every tracked version, in key order.
+    pub(crate) async fn lists_records_in_key_order(store: &dyn MetadataStore) {
+        for fq_key in ["rust/lib:2.0.0", "rust/app:0.1.0", "rust/lib:1.0.0", "npm/lib:1.0.0"] {
+            store.upsert_package(&key(fq_key), update("project", &[])).await.unwrap();
+        }
+        assert_eq!(store.list_versions("rust", "lib").await.unwrap(), ["1.0.0", "2.0.0"]);
+        assert!(store.list_versions("rust", "missing").await.unwrap().is_empty());
+        let fq_keys: Vec<String> = store.list_records().await.unwrap().iter().map(|record| record.key.to_fq_key()).collect();
+        assert_eq!(fq_keys, ["npm/lib:1.0.0", "rust/app:0.1.0", "rust/lib:1.0.0", "rust/lib:2.0.0"]);
+    }
+}
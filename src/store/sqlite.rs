@@ -0,0 +1,318 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::Mutex;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::manifest::DependencyKind;
+use crate::error::Error;
+use crate::package_key::PackageKey;
+use crate::store::{MetadataStore, PackageRecord, PackageUpdate};
+use crate::trigger::BuildTarget;
+
+const RELATION_CONSUMER: &str = "consumer";
+const RELATION_DEPENDENCY: &str = "dependency";
+const KINDS_SEPARATOR: &str = ",";
+
+// Each version owns its own consumer and dependency edges, mirroring the string sets stored on a
+// DynamoDB item. The far end of an edge is a fully qualified key rather than a foreign key
+// because consumers are recorded before their own records are created. Dependency edges also
+// record the comma separated kinds the dependency was declared as.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS packages (
+        id INTEGER PRIMARY KEY,
+        package_name TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE IF NOT EXISTS versions (
+        id INTEGER PRIMARY KEY,
+        package_id INTEGER NOT NULL REFERENCES packages(id) ON DELETE CASCADE,
+        version TEXT NOT NULL,
+        build_trigger TEXT,
+        build_target TEXT,
+        UNIQUE (package_id, version)
+    );
+    CREATE TABLE IF NOT EXISTS edges (
+        version_id INTEGER NOT NULL REFERENCES versions(id) ON DELETE CASCADE,
+        relation TEXT NOT NULL CHECK (relation IN ('consumer', 'dependency')),
+        package_key TEXT NOT NULL,
+        kinds TEXT,
+        PRIMARY KEY (version_id, relation, package_key)
+    );
+";
+
+/// A [`MetadataStore`] persisted in a SQLite database file, for builds that run outside AWS.
+///
+/// Calls are made on the async runtime's thread; SQLite access is local and short-lived enough
+/// that this doesn't warrant a blocking thread pool.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens (creating if needed) the database at `path` and makes sure its schema exists.
+    pub fn open(path: impl AsRef<Path>) -> Result<SqliteStore, Error> {
+        SqliteStore::with_connection(Connection::open(path).map_err(sql_error)?)
+    }
+
+    /// Makes sure the schema of an already open database exists and is up to date.
+    fn with_connection(conn: Connection) -> Result<SqliteStore, Error> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;").map_err(sql_error)?;
+        conn.execute_batch(SCHEMA).map_err(sql_error)?;
+        // Databases created before kinds were tracked don't have the column yet.
+        let has_kinds: bool = conn.query_row("SELECT COUNT(*) > 0 FROM pragma_table_info('edges') WHERE name = 'kinds'",
+                                             [], |row| row.get(0)).map_err(sql_error)?;
+        if !has_kinds {
+            conn.execute_batch("ALTER TABLE edges ADD COLUMN kinds TEXT;").map_err(sql_error)?;
+        }
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().expect("SQLite connection lock was poisoned")
+    }
+}
+
+#[async_trait]
+impl MetadataStore for SqliteStore {
+    async fn add_consumer(&self, dependency: &PackageKey, consumer: &PackageKey) -> Result<bool, Error> {
+        let conn = self.conn();
+        add_edge(&conn, dependency, RELATION_CONSUMER, &consumer.to_fq_key())
+    }
+
+    async fn remove_consumer(&self, dependency: &PackageKey, consumer: &PackageKey) -> Result<bool, Error> {
+        let conn = self.conn();
+        remove_edge(&conn, dependency, RELATION_CONSUMER, &consumer.to_fq_key())
+    }
+
+    async fn remove_dependency(&self, consumer: &PackageKey, dependency: &PackageKey) -> Result<bool, Error> {
+        let conn = self.conn();
+        remove_edge(&conn, consumer, RELATION_DEPENDENCY, &dependency.to_fq_key())
+    }
+
+    async fn upsert_package(&self, package: &PackageKey, update: PackageUpdate) -> Result<Option<PackageRecord>, Error> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(sql_error)?;
+        let old_record = load_record(&tx, package)?;
+
+        tx.execute("INSERT OR IGNORE INTO packages (package_name) VALUES (?1)",
+                   params![package.qualified_name()]).map_err(sql_error)?;
+        tx.execute("INSERT INTO versions (package_id, version, build_trigger, build_target)
+                    SELECT id, ?2, ?3, ?4 FROM packages WHERE package_name = ?1
+                    ON CONFLICT (package_id, version)
+                    DO UPDATE SET build_trigger = excluded.build_trigger, build_target = excluded.build_target",
+                   params![package.qualified_name(), package.version, update.build_target.kind.as_str(), update.build_target.target]).map_err(sql_error)?;
+        let version_id = version_id(&tx, package)?.expect("Expected version to exist after upsert");
+
+        tx.execute("DELETE FROM edges WHERE version_id = ?1 AND relation = ?2",
+                   params![version_id, RELATION_DEPENDENCY]).map_err(sql_error)?;
+        for (dependency, kinds) in &update.dependencies {
+            let kinds: Vec<&str> = kinds.iter().map(|kind| kind.as_str()).collect();
+            tx.execute("INSERT OR IGNORE INTO edges (version_id, relation, package_key, kinds) VALUES (?1, ?2, ?3, ?4)",
+                       params![version_id, RELATION_DEPENDENCY, dependency, kinds.join(KINDS_SEPARATOR)]).map_err(sql_error)?;
+        }
+        tx.commit().map_err(sql_error)?;
+        Ok(old_record)
+    }
+
+    async fn get_record(&self, package: &PackageKey) -> Result<Option<PackageRecord>, Error> {
+        let conn = self.conn();
+        load_record(&conn, package)
+    }
+
+    async fn delete_record(&self, package: &PackageKey) -> Result<Option<PackageRecord>, Error> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(sql_error)?;
+        let old_record = load_record(&tx, package)?;
+        if let Some(version_id) = version_id(&tx, package)? {
+            tx.execute("DELETE FROM versions WHERE id = ?1", params![version_id]).map_err(sql_error)?;
+            tx.execute("DELETE FROM packages WHERE package_name = ?1
+                        AND NOT EXISTS (SELECT 1 FROM versions WHERE versions.package_id = packages.id)",
+                       params![package.qualified_name()]).map_err(sql_error)?;
+        }
+        tx.commit().map_err(sql_error)?;
+        Ok(old_record)
+    }
+
+    async fn list_versions(&self, build_system: &str, name: &str) -> Result<Vec<String>, Error> {
+        let conn = self.conn();
+        let mut statement = conn.prepare(
+            "SELECT v.version FROM versions v JOIN packages p ON p.id = v.package_id
+             WHERE p.package_name = ?1 ORDER BY v.version").map_err(sql_error)?;
+        let versions = statement.query_map(params![format!("{}/{}", build_system, name)], |row| row.get(0))
+            .map_err(sql_error)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(sql_error)?;
+        Ok(versions)
+    }
+
+    async fn list_records(&self) -> Result<Vec<PackageRecord>, Error> {
+        let conn = self.conn();
+        let mut statement = conn.prepare(
+            "SELECT p.package_name, v.version FROM versions v JOIN packages p ON p.id = v.package_id
+             ORDER BY p.package_name, v.version").map_err(sql_error)?;
+        let fq_keys = statement.query_map([], |row| Ok(format!("{}:{}", row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(sql_error)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(sql_error)?;
+        let mut records = vec![];
+        for fq_key in fq_keys {
+            if let Some(record) = load_record(&conn, &PackageKey::from_fq_key(&fq_key)?)? {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+fn version_id(conn: &Connection, pkg_key: &PackageKey) -> Result<Option<i64>, Error> {
+    conn.query_row(
+        "SELECT v.id FROM versions v JOIN packages p ON p.id = v.package_id
+         WHERE p.package_name = ?1 AND v.version = ?2",
+        params![pkg_key.qualified_name(), pkg_key.version],
+        |row| row.get(0)).optional().map_err(sql_error)
+}
+
+/// Adds an edge to an existing version. Returns `false` if the version isn't tracked.
+fn add_edge(conn: &Connection, owner: &PackageKey, relation: &str, package_key: &str) -> Result<bool, Error> {
+    match version_id(conn, owner)? {
+        Some(version_id) => {
+            conn.execute("INSERT OR IGNORE INTO edges (version_id, relation, package_key) VALUES (?1, ?2, ?3)",
+                         params![version_id, relation, package_key]).map_err(sql_error)?;
+            Ok(true)
+        },
+        None => Ok(false)
+    }
+}
+
+/// Removes an edge from an existing version. Returns `false` if the version isn't tracked.
+fn remove_edge(conn: &Connection, owner: &PackageKey, relation: &str, package_key: &str) -> Result<bool, Error> {
+    match version_id(conn, owner)? {
+        Some(version_id) => {
+            conn.execute("DELETE FROM edges WHERE version_id = ?1 AND relation = ?2 AND package_key = ?3",
+                         params![version_id, relation, package_key]).map_err(sql_error)?;
+            Ok(true)
+        },
+        None => Ok(false)
+    }
+}
+
+fn load_record(conn: &Connection, pkg_key: &PackageKey) -> Result<Option<PackageRecord>, Error> {
+    let row: Option<(i64, Option<String>, Option<String>)> = conn.query_row(
+        "SELECT v.id, v.build_trigger, v.build_target FROM versions v JOIN packages p ON p.id = v.package_id
+         WHERE p.package_name = ?1 AND v.version = ?2",
+        params![pkg_key.qualified_name(), pkg_key.version],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).optional().map_err(sql_error)?;
+    let (version_id, build_trigger, build_target) = match row {
+        Some(row) => row,
+        None => return Ok(None)
+    };
+    let build_target = match (build_trigger, build_target) {
+        (Some(kind), Some(target)) => Some(BuildTarget { kind: kind.parse().map_err(|err: String| Error::Store(err.into()))?, target }),
+        _ => None,
+    };
+
+    let mut statement = conn.prepare("SELECT relation, package_key, kinds FROM edges WHERE version_id = ?1")
+        .map_err(sql_error)?;
+    let edges = statement.query_map(params![version_id],
+                                    |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?)))
+        .map_err(sql_error)?;
+    let mut consumers = BTreeSet::new();
+    let mut dependencies = BTreeSet::new();
+    let mut dependency_kinds = BTreeMap::new();
+    for edge in edges {
+        let (relation, package_key, kinds) = edge.map_err(sql_error)?;
+        if relation == RELATION_CONSUMER {
+            consumers.insert(package_key);
+        } else {
+            if let Some(kinds) = kinds {
+                let kinds: BTreeSet<DependencyKind> = kinds.split(KINDS_SEPARATOR)
+                    .filter_map(|kind| kind.parse().ok())
+                    .collect();
+                dependency_kinds.insert(package_key.clone(), kinds);
+            }
+            dependencies.insert(package_key);
+        }
+    }
+    Ok(Some(PackageRecord {
+        key: pkg_key.clone(),
+        build_target,
+        consumers,
+        dependencies,
+        dependency_kinds,
+    }))
+}
+
+fn sql_error(err: rusqlite::Error) -> Error {
+    Error::Store(Box::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::contract;
+
+    fn store() -> SqliteStore {
+        SqliteStore::open(":memory:").unwrap()
+    }
+
+    #[tokio::test]
+    async fn edge_writes_need_a_tracked_record() {
+        contract::edge_writes_need_a_tracked_record(&store()).await;
+    }
+
+    #[tokio::test]
+    async fn upserts_keep_consumers() {
+        contract::upserts_keep_consumers(&store()).await;
+    }
+
+    #[tokio::test]
+    async fn deletes_return_the_record_once() {
+        contract::deletes_return_the_record_once(&store()).await;
+    }
+
+    #[tokio::test]
+    async fn lists_records_in_key_order() {
+        contract::lists_records_in_key_order(&store()).await;
+    }
+
+    #[tokio::test]
+    async fn opens_a_database_created_before_kinds_were_tracked() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            CREATE TABLE packages (id INTEGER PRIMARY KEY, package_name TEXT NOT NULL UNIQUE);
+            CREATE TABLE versions (
+                id INTEGER PRIMARY KEY,
+                package_id INTEGER NOT NULL REFERENCES packages(id) ON DELETE CASCADE,
+                version TEXT NOT NULL,
+                build_trigger TEXT,
+                build_target TEXT,
+                UNIQUE (package_id, version)
+            );
+            CREATE TABLE edges (
+                version_id INTEGER NOT NULL REFERENCES versions(id) ON DELETE CASCADE,
+                relation TEXT NOT NULL CHECK (relation IN ('consumer', 'dependency')),
+                package_key TEXT NOT NULL,
+                PRIMARY KEY (version_id, relation, package_key)
+            );
+            INSERT INTO packages (id, package_name) VALUES (1, 'rust/app');
+            INSERT INTO versions (id, package_id, version, build_trigger, build_target) VALUES (1, 1, '0.1.0', 'codebuild', 'app-project');
+            INSERT INTO edges (version_id, relation, package_key) VALUES (1, 'dependency', 'rust/lib:1.0.0');
+        ").unwrap();
+
+        let store = SqliteStore::with_connection(conn).unwrap();
+        let app = PackageKey::from_fq_key("rust/app:0.1.0").unwrap();
+        let record = store.get_record(&app).await.unwrap().unwrap();
+        assert_eq!(record.dependencies, BTreeSet::from([String::from("rust/lib:1.0.0")]));
+        assert!(record.dependency_kinds.is_empty());
+        assert_eq!(record.kinds_of("rust/lib:1.0.0"), BTreeSet::from([DependencyKind::Normal]));
+
+        let update = PackageUpdate {
+            build_target: record.build_target.unwrap(),
+            dependencies: BTreeMap::from([(String::from("rust/lib:1.0.0"), BTreeSet::from([DependencyKind::Build]))]),
+        };
+        store.upsert_package(&app, update).await.unwrap();
+        let record = store.get_record(&app).await.unwrap().unwrap();
+        assert_eq!(record.kinds_of("rust/lib:1.0.0"), BTreeSet::from([DependencyKind::Build]));
+    }
+}
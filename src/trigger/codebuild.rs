@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use aws_config::Config;
+use aws_sdk_codebuild::{Client as CodeBuildClient, SdkError};
+use aws_sdk_codebuild::error::StartBuildErrorKind;
+use aws_sdk_codebuild::model::{EnvironmentVariable, EnvironmentVariableType, StatusType};
+use crate::error::Error;
+use crate::package_key::PackageKey;
+use crate::trigger::{BuildStatus, BuildTarget, BuildTrigger, TriggeredBuild, ENV_SKIP_CONSUMER_REBUILDS};
+
+/// The most build ids BatchGetBuilds accepts in one request.
+const MAX_BATCH_SIZE: usize = 100;
+const CODEBUILD: &str = "codebuild";
+const STATUS_NOT_FOUND: &str = "NOT_FOUND";
+
+/// Starts a build of the CodeBuild project named by the target. Skipping consumer rebuilds sets
+/// `PKG_SKIP_CONSUMER_REBUILDS` in the build's environment.
+pub struct CodeBuildTrigger {
+    codebuild: CodeBuildClient,
+}
+
+impl CodeBuildTrigger {
+    pub fn new(client_config: &Config) -> CodeBuildTrigger {
+        CodeBuildTrigger {
+            codebuild: CodeBuildClient::new(client_config),
+        }
+    }
+}
+
+#[async_trait]
+impl BuildTrigger for CodeBuildTrigger {
+    async fn trigger(&self, target: &BuildTarget, _consumer: &PackageKey, _dependency: &PackageKey, skip_consumer_rebuilds: bool) -> Result<Option<TriggeredBuild>, Error> {
+        let mut start_build = self.codebuild.start_build().project_name(&target.target);
+        if skip_consumer_rebuilds {
+            start_build = start_build.environment_variables_override(EnvironmentVariable::builder()
+                .name(ENV_SKIP_CONSUMER_REBUILDS)
+                .value(true.to_string())
+                .r#type(EnvironmentVariableType::Plaintext)
+                .build());
+        }
+        match start_build.send().await {
+            Ok(response) => Ok(Some(TriggeredBuild {
+                build_id: response.build_value.and_then(|build| build.id),
+            })),
+            Err(SdkError::ServiceError { err, .. }) if matches!(err.kind, StartBuildErrorKind::ResourceNotFoundException(_)) => Ok(None),
+            Err(SdkError::ServiceError { err, .. }) => Err(Error::Trigger { target: target.to_string(), source: Box::new(err) }),
+            Err(err) => Err(Error::Trigger { target: target.to_string(), source: err.to_string().into() })
+        }
+    }
+
+    async fn statuses(&self, build_ids: &[String]) -> Result<HashMap<String, BuildStatus>, Error> {
+        let mut statuses = HashMap::new();
+        for batch in build_ids.chunks(MAX_BATCH_SIZE) {
+            let response = match self.codebuild.batch_get_builds().set_ids(Some(batch.to_vec())).send().await {
+                Ok(response) => response,
+                Err(SdkError::ServiceError { err, .. }) => return Err(Error::Trigger { target: String::from(CODEBUILD), source: Box::new(err) }),
+                Err(err) => return Err(Error::Trigger { target: String::from(CODEBUILD), source: err.to_string().into() })
+            };
+            for build in response.builds.unwrap_or_default() {
+                let status = match build.build_status {
+                    Some(StatusType::InProgress) | None => BuildStatus::InProgress,
+                    Some(StatusType::Succeeded) => BuildStatus::Succeeded,
+                    Some(status) => BuildStatus::Failed(status.as_str().to_string()),
+                };
+                if let Some(build_id) = build.id {
+                    statuses.insert(build_id, status);
+                }
+            }
+            for build_id in response.builds_not_found.unwrap_or_default() {
+                statuses.insert(build_id, BuildStatus::Failed(String::from(STATUS_NOT_FOUND)));
+            }
+        }
+        Ok(statuses)
+    }
+}
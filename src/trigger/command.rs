@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+use tokio::process::Command;
+use crate::error::Error;
+use crate::package_key::PackageKey;
+use crate::trigger::{BuildTarget, BuildTrigger, TriggeredBuild, ENV_SKIP_CONSUMER_REBUILDS};
+
+const ENV_CONSUMER: &str = "PKG_CONSUMER";
+const ENV_DEPENDENCY: &str = "PKG_DEPENDENCY";
+
+/// Runs the target as a shell command and waits for it to finish. The consumer and the
+/// dependency that caused the rebuild are passed in the `PKG_CONSUMER` and `PKG_DEPENDENCY` env
+/// variables as fully qualified keys, and whether to skip consumer rebuilds in
+/// `PKG_SKIP_CONSUMER_REBUILDS`.
+#[derive(Default)]
+pub struct CommandTrigger;
+
+impl CommandTrigger {
+    pub fn new() -> CommandTrigger {
+        CommandTrigger
+    }
+}
+
+#[async_trait]
+impl BuildTrigger for CommandTrigger {
+    async fn trigger(&self, target: &BuildTarget, consumer: &PackageKey, dependency: &PackageKey, skip_consumer_rebuilds: bool) -> Result<Option<TriggeredBuild>, Error> {
+        let status = match Command::new("sh")
+            .arg("-c")
+            .arg(&target.target)
+            .env(ENV_CONSUMER, consumer.to_fq_key())
+            .env(ENV_DEPENDENCY, dependency.to_fq_key())
+            .env(ENV_SKIP_CONSUMER_REBUILDS, skip_consumer_rebuilds.to_string())
+            .status().await {
+            Ok(status) => status,
+            Err(err) => return Err(Error::Trigger { target: target.to_string(), source: Box::new(err) })
+        };
+        if status.success() {
+            Ok(Some(TriggeredBuild { build_id: None }))
+        } else {
+            Err(Error::Trigger { target: target.to_string(), source: format!("command failed with {}", status).into() })
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use async_trait::async_trait;
+use crate::error::Error;
+use crate::package_key::PackageKey;
+use crate::trigger::{BuildStatus, BuildTarget, BuildTrigger, TriggeredBuild};
+
+const STATUS_FAILED: &str = "FAILED";
+
+/// A local stand-in for CodeBuild. Builds get ids of the form "ProjectName:N" like real ones, are
+/// in progress for a set number of status checks and then finish. Builds of the projects marked
+/// as failing fail; every other build succeeds.
+pub struct FakeCodeBuildTrigger {
+    checks_until_done: u32,
+    failing_projects: HashSet<String>,
+    /// How many more times each started build is reported in progress.
+    builds: Mutex<HashMap<String, (String, u32)>>,
+}
+
+impl FakeCodeBuildTrigger {
+    pub fn new(checks_until_done: u32) -> FakeCodeBuildTrigger {
+        FakeCodeBuildTrigger {
+            checks_until_done,
+            failing_projects: HashSet::new(),
+            builds: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Makes every build of `project` fail.
+    pub fn with_failing_project(mut self, project: impl Into<String>) -> FakeCodeBuildTrigger {
+        self.failing_projects.insert(project.into());
+        self
+    }
+
+    /// The projects builds were started for, sorted.
+    pub fn started(&self) -> Vec<String> {
+        let builds = self.builds.lock().expect("Fake CodeBuild lock was poisoned");
+        let mut started: Vec<String> = builds.values().map(|(project, _)| project.clone()).collect();
+        started.sort();
+        started
+    }
+}
+
+#[async_trait]
+impl BuildTrigger for FakeCodeBuildTrigger {
+    async fn trigger(&self, target: &BuildTarget, _consumer: &PackageKey, _dependency: &PackageKey, _skip_consumer_rebuilds: bool) -> Result<Option<TriggeredBuild>, Error> {
+        let mut builds = self.builds.lock().expect("Fake CodeBuild lock was poisoned");
+        let build_id = format!("{}:{}", target.target, builds.len() + 1);
+        builds.insert(build_id.clone(), (target.target.clone(), self.checks_until_done));
+        Ok(Some(TriggeredBuild { build_id: Some(build_id) }))
+    }
+
+    async fn statuses(&self, build_ids: &[String]) -> Result<HashMap<String, BuildStatus>, Error> {
+        let mut builds = self.builds.lock().expect("Fake CodeBuild lock was poisoned");
+        let mut statuses = HashMap::new();
+        for build_id in build_ids {
+            let (project, checks_left) = match builds.get_mut(build_id) {
+                Some(build) => build,
+                None => continue
+            };
+            let status = if *checks_left > 0 {
+                *checks_left -= 1;
+                BuildStatus::InProgress
+            } else if self.failing_projects.contains(project.as_str()) {
+                BuildStatus::Failed(String::from(STATUS_FAILED))
+            } else {
+                BuildStatus::Succeeded
+            };
+            statuses.insert(build_id.clone(), status);
+        }
+        Ok(statuses)
+    }
+}
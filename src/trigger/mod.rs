@@ -0,0 +1,170 @@
+mod codebuild;
+mod command;
+#[cfg(test)]
+mod fake;
+mod recorder;
+mod webhook;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use async_trait::async_trait;
+use aws_config::Config;
+use crate::error::Error;
+use crate::package_key::PackageKey;
+
+pub use codebuild::CodeBuildTrigger;
+pub use command::CommandTrigger;
+#[cfg(test)]
+pub(crate) use fake::FakeCodeBuildTrigger;
+pub use recorder::{RecordedBuild, RecordingTrigger};
+pub use webhook::WebhookTrigger;
+
+const KIND_CODEBUILD: &str = "codebuild";
+const KIND_COMMAND: &str = "command";
+const KIND_WEBHOOK: &str = "webhook";
+const KIND_NOOP: &str = "noop";
+
+/// Set to "true" in a triggered build's environment when the updater that started it is
+/// rebuilding the consumers itself, so `register` in that build shouldn't rebuild them again.
+pub const ENV_SKIP_CONSUMER_REBUILDS: &str = "PKG_SKIP_CONSUMER_REBUILDS";
+
+/// How a package's build is started.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TriggerKind {
+    /// `target` is the name of a CodeBuild project.
+    CodeBuild,
+    /// `target` is a shell command.
+    Command,
+    /// `target` is a URL that receives a POST.
+    Webhook,
+    /// Nothing is started; the request is only recorded.
+    Noop,
+}
+
+impl TriggerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TriggerKind::CodeBuild => KIND_CODEBUILD,
+            TriggerKind::Command => KIND_COMMAND,
+            TriggerKind::Webhook => KIND_WEBHOOK,
+            TriggerKind::Noop => KIND_NOOP,
+        }
+    }
+}
+
+impl fmt::Display for TriggerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TriggerKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            KIND_CODEBUILD => Ok(TriggerKind::CodeBuild),
+            KIND_COMMAND => Ok(TriggerKind::Command),
+            KIND_WEBHOOK => Ok(TriggerKind::Webhook),
+            KIND_NOOP => Ok(TriggerKind::Noop),
+            _ => Err(format!("Unknown build trigger \"{}\". Expected one of {}, {}, {} or {}.", kind, KIND_CODEBUILD, KIND_COMMAND, KIND_WEBHOOK, KIND_NOOP)),
+        }
+    }
+}
+
+/// What gets fired to rebuild a package, as recorded on its record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildTarget {
+    pub kind: TriggerKind,
+    pub target: String,
+}
+
+impl fmt::Display for BuildTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.target)
+    }
+}
+
+/// A build that was started by a [`BuildTrigger`].
+#[derive(Clone, Debug)]
+pub struct TriggeredBuild {
+    /// The id of the started build, for triggers that have one.
+    pub build_id: Option<String>,
+}
+
+/// How far along a triggered build is.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BuildStatus {
+    InProgress,
+    Succeeded,
+    /// The build finished without succeeding, with the status reported for it (e.g. "FAILED" or
+    /// "TIMED_OUT").
+    Failed(String),
+}
+
+impl fmt::Display for BuildStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildStatus::InProgress => f.write_str("in progress"),
+            BuildStatus::Succeeded => f.write_str("succeeded"),
+            BuildStatus::Failed(status) => f.write_str(status),
+        }
+    }
+}
+
+/// Starts a rebuild of a consumer because one of its dependencies changed.
+#[async_trait]
+pub trait BuildTrigger: Send + Sync {
+    /// Starts a build of `target` on behalf of `consumer`. Returns `None` if `target` doesn't
+    /// exist, in which case the rebuild is skipped. `skip_consumer_rebuilds` is passed on to the
+    /// build (see [`ENV_SKIP_CONSUMER_REBUILDS`]) when the caller rebuilds the consumer's own
+    /// consumers.
+    async fn trigger(&self, target: &BuildTarget, consumer: &PackageKey, dependency: &PackageKey, skip_consumer_rebuilds: bool) -> Result<Option<TriggeredBuild>, Error>;
+
+    /// Fetches the status of builds this trigger started, by build id. Triggers that don't hand
+    /// out build ids never need to answer, so by default every build has succeeded.
+    async fn statuses(&self, build_ids: &[String]) -> Result<HashMap<String, BuildStatus>, Error> {
+        Ok(build_ids.iter().map(|build_id| (build_id.clone(), BuildStatus::Succeeded)).collect())
+    }
+}
+
+/// The [`BuildTrigger`] used for each [`TriggerKind`].
+#[derive(Default)]
+pub struct BuildTriggers {
+    triggers: HashMap<TriggerKind, Arc<dyn BuildTrigger>>,
+}
+
+impl BuildTriggers {
+    pub fn new() -> BuildTriggers {
+        BuildTriggers::default()
+    }
+
+    /// Every kind mapped to the same trigger.
+    pub fn all(trigger: Arc<dyn BuildTrigger>) -> BuildTriggers {
+        [TriggerKind::CodeBuild, TriggerKind::Command, TriggerKind::Webhook, TriggerKind::Noop].into_iter()
+            .fold(BuildTriggers::new(), |triggers, kind| triggers.with(kind, trigger.clone()))
+    }
+
+    /// Every kind mapped to its real implementation.
+    pub fn standard(client_config: &Config) -> BuildTriggers {
+        BuildTriggers::new()
+            .with(TriggerKind::CodeBuild, Arc::new(CodeBuildTrigger::new(client_config)))
+            .with(TriggerKind::Command, Arc::new(CommandTrigger::new()))
+            .with(TriggerKind::Webhook, Arc::new(WebhookTrigger::new()))
+            .with(TriggerKind::Noop, Arc::new(RecordingTrigger::new()))
+    }
+
+    pub fn with(mut self, kind: TriggerKind, trigger: Arc<dyn BuildTrigger>) -> BuildTriggers {
+        self.triggers.insert(kind, trigger);
+        self
+    }
+
+    pub fn for_kind(&self, kind: TriggerKind) -> Result<&dyn BuildTrigger, Error> {
+        match self.triggers.get(&kind) {
+            Some(trigger) => Ok(trigger.as_ref()),
+            None => Err(Error::TriggerNotConfigured(kind.to_string()))
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::Mutex;
+use async_trait::async_trait;
+use crate::error::Error;
+use crate::package_key::PackageKey;
+use crate::trigger::{BuildTarget, BuildTrigger, TriggeredBuild};
+
+/// A rebuild captured by a [`RecordingTrigger`].
+#[derive(Clone, Debug)]
+pub struct RecordedBuild {
+    pub target: BuildTarget,
+    pub consumer: PackageKey,
+    pub dependency: PackageKey,
+    pub skip_consumer_rebuilds: bool,
+}
+
+/// Starts nothing and remembers every rebuild it was asked for.
+#[derive(Default)]
+pub struct RecordingTrigger {
+    recorded: Mutex<Vec<RecordedBuild>>,
+}
+
+impl RecordingTrigger {
+    pub fn new() -> RecordingTrigger {
+        RecordingTrigger::default()
+    }
+
+    /// The rebuilds recorded so far, in the order they were requested.
+    pub fn recorded(&self) -> Vec<RecordedBuild> {
+        self.recorded.lock().expect("Recorder lock was poisoned").clone()
+    }
+}
+
+#[async_trait]
+impl BuildTrigger for RecordingTrigger {
+    async fn trigger(&self, target: &BuildTarget, consumer: &PackageKey, dependency: &PackageKey, skip_consumer_rebuilds: bool) -> Result<Option<TriggeredBuild>, Error> {
+        eprintln!("Recorded rebuild of {} ({}) due to {}.", consumer.to_fq_key(), target, dependency.to_fq_key());
+        self.recorded.lock().expect("Recorder lock was poisoned").push(RecordedBuild {
+            target: target.clone(),
+            consumer: consumer.clone(),
+            dependency: dependency.clone(),
+            skip_consumer_rebuilds,
+        });
+        Ok(Some(TriggeredBuild { build_id: None }))
+    }
+}
@@ -0,0 +1,39 @@
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use crate::error::Error;
+use crate::package_key::PackageKey;
+use crate::trigger::{BuildTarget, BuildTrigger, TriggeredBuild};
+
+/// POSTs `{"consumer": "<fq key>", "dependency": "<fq key>", "skip_consumer_rebuilds": <bool>}`
+/// to the target URL. A 404 is treated like a missing CodeBuild project and skipped; any other
+/// non-success status fails.
+#[derive(Default)]
+pub struct WebhookTrigger {
+    client: Client,
+}
+
+impl WebhookTrigger {
+    pub fn new() -> WebhookTrigger {
+        WebhookTrigger::default()
+    }
+}
+
+#[async_trait]
+impl BuildTrigger for WebhookTrigger {
+    async fn trigger(&self, target: &BuildTarget, consumer: &PackageKey, dependency: &PackageKey, skip_consumer_rebuilds: bool) -> Result<Option<TriggeredBuild>, Error> {
+        let body = serde_json::json!({
+            "consumer": consumer.to_fq_key(),
+            "dependency": dependency.to_fq_key(),
+            "skip_consumer_rebuilds": skip_consumer_rebuilds,
+        });
+        let response = match self.client.post(&target.target).json(&body).send().await {
+            Ok(response) => response,
+            Err(err) => return Err(Error::Trigger { target: target.to_string(), source: Box::new(err) })
+        };
+        match response.status() {
+            status if status.is_success() => Ok(Some(TriggeredBuild { build_id: None })),
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(Error::Trigger { target: target.to_string(), source: format!("webhook responded with {}", status).into() })
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use cargo_toml::Manifest;
+use crate::crate_helper::CrateHelper;
+use crate::manifest::{manifest_dir, ManifestReader};
+use crate::error::Error;
+
+const MANIFEST_FILE_NAME: &str = "Cargo.toml";
+
+/// The member crates of a Cargo workspace.
+pub struct Workspace {
+    /// Members ordered so that every member comes after the members it depends on. Path
+    /// dependencies on other members carry that member's exact version.
+    pub members: Vec<CrateHelper>,
+}
+
+impl Workspace {
+    /// Reads the workspace whose root manifest is at `cargo_toml_path`. Returns `None` if the
+    /// manifest has no `[workspace]` section.
+    ///
+    /// Members are the root package (if the root manifest has one) plus every directory matched
+    /// by `members` that isn't under a path in `exclude` and contains a Cargo.toml.
+    pub fn from_path(cargo_toml_path: impl AsRef<Path>) -> Result<Option<Workspace>, Error> {
+        let cargo_toml_path = cargo_toml_path.as_ref();
+        let manifest = match Manifest::from_path(cargo_toml_path) {
+            Ok(manifest) => manifest,
+            Err(err) => return Err(Error::Manifest {
+                path: cargo_toml_path.to_path_buf(),
+                source: Box::new(err),
+            })
+        };
+        let workspace = match manifest.workspace {
+            Some(workspace) => workspace,
+            None => return Ok(None)
+        };
+
+        let root_dir = manifest_dir(cargo_toml_path);
+        let excluded: Vec<PathBuf> = workspace.exclude.iter()
+            .map(|exclude| canonical(&root_dir.join(exclude)))
+            .collect();
+        let mut member_dirs = vec![];
+        if manifest.package.is_some() {
+            member_dirs.push(canonical(&root_dir));
+        }
+        for member in &workspace.members {
+            for member_dir in expand_member(cargo_toml_path, &root_dir, member)? {
+                let member_dir = canonical(&member_dir);
+                if member_dir.join(MANIFEST_FILE_NAME).is_file()
+                    && !excluded.iter().any(|exclude| member_dir.starts_with(exclude))
+                    && !member_dirs.contains(&member_dir) {
+                    member_dirs.push(member_dir);
+                }
+            }
+        }
+
+        let mut members = vec![];
+        for member_dir in &member_dirs {
+            members.push(CrateHelper::from_path(member_dir.join(MANIFEST_FILE_NAME))?);
+        }
+        pin_member_versions(&member_dirs, &mut members);
+        Ok(Some(Workspace {
+            members: dependency_order(&member_dirs, members),
+        }))
+    }
+}
+
+fn expand_member(cargo_toml_path: &Path, root_dir: &Path, member: &str) -> Result<Vec<PathBuf>, Error> {
+    let pattern = root_dir.join(member);
+    let invalid_pattern = |reason: String| Error::InvalidManifest {
+        path: cargo_toml_path.to_path_buf(),
+        reason: format!("Invalid workspace member \"{}\": {}", member, reason),
+    };
+    let paths = match glob::glob(&pattern.to_string_lossy()) {
+        Ok(paths) => paths,
+        Err(err) => return Err(invalid_pattern(err.to_string()))
+    };
+    let mut member_dirs = vec![];
+    for path in paths {
+        match path {
+            Ok(path) => member_dirs.push(path),
+            Err(err) => return Err(invalid_pattern(err.to_string()))
+        }
+    }
+    member_dirs.sort();
+    Ok(member_dirs)
+}
+
+/// Points path dependencies on other members at those members' names and exact versions, so that
+/// they become edges between the members' records.
+fn pin_member_versions(member_dirs: &[PathBuf], members: &mut [CrateHelper]) {
+    let member_packages: HashMap<PathBuf, (String, String)> = member_dirs.iter()
+        .zip(members.iter())
+        .map(|(member_dir, member)| (member_dir.clone(), (member.name(), member.version())))
+        .collect();
+    for member in members.iter_mut() {
+        for dep in member.dependencies.iter_mut() {
+            let package = dep.path.as_ref().and_then(|path| member_packages.get(&canonical(path)));
+            if let Some((name, version)) = package {
+                dep.name = name.clone();
+                dep.version = Some(version.clone());
+                dep.locked_version = Some(version.clone());
+            }
+        }
+    }
+}
+
+/// Orders members so that each comes after the members it has path dependencies on.
+fn dependency_order(member_dirs: &[PathBuf], members: Vec<CrateHelper>) -> Vec<CrateHelper> {
+    let index_by_dir: HashMap<&PathBuf, usize> = member_dirs.iter()
+        .enumerate()
+        .map(|(index, member_dir)| (member_dir, index))
+        .collect();
+    let member_deps: Vec<Vec<usize>> = members.iter()
+        .map(|member| member.dependencies.iter()
+            .filter_map(|dep| dep.path.as_ref())
+            .filter_map(|path| index_by_dir.get(&canonical(path)).copied())
+            .collect())
+        .collect();
+
+    let mut order = vec![];
+    let mut visited = vec![false; members.len()];
+    for index in 0..members.len() {
+        visit(index, &member_deps, &mut visited, &mut order);
+    }
+    let mut members: Vec<Option<CrateHelper>> = members.into_iter().map(Some).collect();
+    order.into_iter()
+        .map(|index| members[index].take().expect("Expected each member to be ordered once"))
+        .collect()
+}
+
+fn visit(index: usize, member_deps: &[Vec<usize>], visited: &mut [bool], order: &mut Vec<usize>) {
+    // A member that's already been visited is either ordered or on the current path. The latter
+    // only happens for cycles through dev-dependencies, which Cargo allows; those members are
+    // ordered as best we can by skipping the back edge.
+    if visited[index] {
+        return;
+    }
+    visited[index] = true;
+    for dep_index in &member_deps[index] {
+        visit(*dep_index, member_deps, visited, order);
+    }
+    order.push(index);
+}
+
+/// Whether the Cargo.toml in `dir`, if there is one, has a `[workspace]` section.
+pub(crate) fn is_workspace_root(dir: &Path) -> bool {
+    fs::read_to_string(dir.join(MANIFEST_FILE_NAME)).ok()
+        .and_then(|contents| toml::from_str::<toml::Value>(&contents).ok())
+        .is_some_and(|manifest| manifest.get("workspace").is_some())
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_project;
+
+    fn member_names(workspace: &Workspace) -> Vec<String> {
+        workspace.members.iter().map(|member| member.name()).collect()
+    }
+
+    #[test]
+    fn expands_member_globs_in_dependency_order() {
+        let dir = temp_project("workspace-globs", &[
+            ("Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\n"),
+            ("crates/app/Cargo.toml", "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nlib = { path = \"../lib\" }\n"),
+            ("crates/lib/Cargo.toml", "[package]\nname = \"lib\"\nversion = \"1.2.3\"\n"),
+            ("crates/docs/README.md", "Not a crate."),
+        ]);
+        let workspace = Workspace::from_path(dir.join("Cargo.toml")).unwrap().unwrap();
+        assert_eq!(member_names(&workspace), ["lib", "app"]);
+        let dep = &workspace.members[1].dependencies[0];
+        assert_eq!(dep.name, "lib");
+        assert_eq!(dep.locked_version.as_deref(), Some("1.2.3"));
+    }
+
+    #[test]
+    fn leaves_out_excluded_members() {
+        let dir = temp_project("workspace-exclude", &[
+            ("Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"crates/scratch\"]\n"),
+            ("crates/lib/Cargo.toml", "[package]\nname = \"lib\"\nversion = \"1.0.0\"\n"),
+            ("crates/scratch/Cargo.toml", "[package]\nname = \"scratch\"\nversion = \"0.0.0\"\n"),
+        ]);
+        let workspace = Workspace::from_path(dir.join("Cargo.toml")).unwrap().unwrap();
+        assert_eq!(member_names(&workspace), ["lib"]);
+    }
+
+    #[test]
+    fn includes_a_root_crate_that_is_also_the_workspace() {
+        let dir = temp_project("workspace-root", &[
+            ("Cargo.toml", "[package]\nname = \"root\"\nversion = \"2.0.0\"\n\n[dependencies]\nhelper = { path = \"helper\" }\n\n[workspace]\nmembers = [\"helper\"]\n"),
+            ("helper/Cargo.toml", "[package]\nname = \"helper\"\nversion = \"0.3.0\"\n"),
+        ]);
+        let workspace = Workspace::from_path(dir.join("Cargo.toml")).unwrap().unwrap();
+        assert_eq!(member_names(&workspace), ["helper", "root"]);
+        assert_eq!(workspace.members[1].dependencies[0].locked_version.as_deref(), Some("0.3.0"));
+    }
+
+    #[test]
+    fn a_single_crate_is_not_a_workspace() {
+        let dir = temp_project("workspace-none", &[("Cargo.toml", "[package]\nname = \"solo\"\nversion = \"1.0.0\"\n")]);
+        assert!(Workspace::from_path(dir.join("Cargo.toml")).unwrap().is_none());
+    }
+}